@@ -0,0 +1,176 @@
+/*
+    In-memory text buffer that engine output can be applied to,
+    as if it were typed into a text editor. A transcript is a buffer that also
+    keeps when each part of the text was written.
+*/
+
+use crate::engine::Action;
+use enigo::Key;
+use std::time::SystemTime;
+
+// The text of the actions, leaving out keys and commands.
+pub fn actions_text(actions: &[Action]) -> String {
+    actions
+        .iter()
+        .filter_map(|x| match x {
+            Action::Text(s) => Some(s.as_str()),
+            _ => None,
+        })
+        .collect()
+}
+
+#[derive(Default)]
+pub struct Buffer {
+    text: String,
+}
+impl Buffer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    // Undo the deleted actions and perform the added ones. Returns the length
+    // of the text at the start that was left as it was.
+    pub fn apply(&mut self, del: &[Action], add: &[Action]) -> usize {
+        let mut kept = self.text.len();
+        for a in del {
+            if let Action::Text(s) = a {
+                for _ in s.chars() {
+                    self.text.pop();
+                }
+            }
+        }
+        kept = kept.min(self.text.len());
+        for a in add {
+            match a {
+                Action::Text(s) => self.text.push_str(s),
+                // Only keys that edit text can be applied, other keys and
+                // modifiers are ignored.
+                Action::KeyClick(k) => match k {
+                    Key::Backspace => {
+                        self.text.pop();
+                        kept = kept.min(self.text.len());
+                    }
+                    Key::Return => self.text.push('\n'),
+                    Key::Tab => self.text.push('\t'),
+                    _ => {}
+                },
+                Action::KeyDown(_) | Action::KeyUp(_) | Action::Command(_) => {}
+            }
+        }
+        kept
+    }
+}
+
+// A word in a transcript, with when its first and last strokes were written.
+#[derive(Debug, PartialEq)]
+pub struct TimedWord<'a> {
+    pub text: &'a str,
+    pub start: SystemTime,
+    pub end: SystemTime,
+}
+
+// Text added by one stroke, from a byte offset to the start of the next one.
+struct Segment {
+    offset: usize,
+    // Text that replaced earlier text, like a word with a suffix, was
+    // started when the text it replaced was
+    start: SystemTime,
+    end: SystemTime,
+}
+
+#[derive(Default)]
+pub struct Transcript {
+    buffer: Buffer,
+    segments: Vec<Segment>,
+}
+impl Transcript {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn text(&self) -> &str {
+        self.buffer.text()
+    }
+
+    // Apply a stroke's actions, returning the length of the text at the
+    // start that was left as it was.
+    pub fn apply(&mut self, time: SystemTime, del: &[Action], add: &[Action]) -> usize {
+        let kept = self.buffer.apply(del, add);
+        let mut start = time;
+        while let Some(segment) = self.segments.last() {
+            if segment.offset < kept {
+                break;
+            }
+            start = start.min(segment.start);
+            self.segments.pop();
+        }
+        if self.text().len() > kept {
+            self.segments.push(Segment {
+                offset: kept,
+                start,
+                end: time,
+            });
+        } else if let Some(segment) = self.segments.last_mut() {
+            // Only taken back from
+            segment.end = time;
+        }
+        kept
+    }
+
+    // The words written, split on whitespace.
+    pub fn words(&self) -> Vec<TimedWord<'_>> {
+        let text = self.text();
+        text.split_whitespace()
+            .map(|word| {
+                let offset = word.as_ptr() as usize - text.as_ptr() as usize;
+                let first = self.segments.partition_point(|x| x.offset <= offset) - 1;
+                let last = self
+                    .segments
+                    .partition_point(|x| x.offset < offset + word.len());
+                let segments = &self.segments[first..last];
+                TimedWord {
+                    text: word,
+                    start: segments.iter().map(|x| x.start).min().unwrap(),
+                    end: segments.iter().map(|x| x.end).max().unwrap(),
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn transcript() {
+        let at = |s| SystemTime::UNIX_EPOCH + Duration::from_secs(s);
+        let text = |s: &str| vec![Action::Text(s.to_string())];
+        let mut transcript = Transcript::new();
+        assert_eq!(transcript.apply(at(0), &[], &text("The")), 0);
+        transcript.apply(at(1), &[], &text(" cat"));
+        // A suffix replaces the word, which was still started by the first stroke
+        assert_eq!(transcript.apply(at(2), &text(" cat"), &text(" cats")), 3);
+        transcript.apply(at(3), &[], &text(" sat"));
+        // A misstroke taken back leaves no trace
+        transcript.apply(at(4), &[], &text(" on"));
+        assert_eq!(transcript.apply(at(5), &text(" on"), &[]), 12);
+        transcript.apply(at(6), &[], &[Action::KeyClick(Key::Backspace)]);
+
+        assert_eq!(transcript.text(), "The cats sa");
+        let words: Vec<(&str, u64, u64)> = transcript
+            .words()
+            .iter()
+            .map(|x| {
+                let secs = |t: SystemTime| t.duration_since(at(0)).unwrap().as_secs();
+                (x.text, secs(x.start), secs(x.end))
+            })
+            .collect();
+        assert_eq!(words, [("The", 0, 0), ("cats", 1, 2), ("sa", 3, 6)]);
+    }
+}
@@ -0,0 +1,576 @@
+/*
+    Config file, read from <config dir>/moreover.toml unless another is given.
+    Everything has a default, and problems are collected up front so they can
+    all be reported at once.
+    The top level settings are the default profile, and named profiles can
+    replace some of them while the engine is running.
+*/
+
+use directories::ProjectDirs;
+use moreover::keyboard::DEFAULT_PASTE_LENGTH;
+use moreover::machine::{Keymap, Mapping};
+use moreover::window::Window;
+use regex::Regex;
+use serde::{Deserialize, Deserializer};
+use std::collections::BTreeMap;
+use std::fs;
+use std::net::ToSocketAddrs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+pub const DEFAULT_PROFILE: &str = "default";
+
+#[derive(Deserialize, Default, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    // Highest priority last
+    pub dictionaries: Vec<String>,
+    pub machine: Option<String>,
+    pub keymap: Option<KeymapConfig>,
+    pub output: Output,
+    pub orthography: Orthography,
+    // File the paper tape is also written to
+    pub tape: Option<String>,
+    // File the transcript is kept in as it's written and corrected
+    pub transcript: Option<String>,
+    // Whether strokes are logged to the data directory, which is on by default
+    pub stroke_log: Option<bool>,
+    pub drill: Drill,
+    pub control: Control,
+    pub websocket: Websocket,
+    pub profiles: BTreeMap<String, Profile>,
+    // Changes to the settings while some windows are focused, of which the
+    // first matching one is used
+    pub windows: Vec<WindowRule>,
+}
+
+// Settings that replace the default ones when the profile is switched to.
+#[derive(Deserialize, Default, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct Profile {
+    pub dictionaries: Option<Vec<String>>,
+    pub keymap: Option<KeymapConfig>,
+    pub output: Option<Output>,
+    pub orthography: Option<Orthography>,
+}
+
+// Changes to the settings of the current profile, for windows whose class and
+// title match.
+#[derive(Deserialize, Default, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct WindowRule {
+    // Found anywhere in the class or title
+    pub class: Option<Pattern>,
+    pub title: Option<Pattern>,
+    // Added above the profile's dictionaries, highest priority last
+    pub dictionaries: Vec<String>,
+    // The profile's dictionaries that are left out
+    pub disable: Vec<String>,
+    pub output: Option<Output>,
+}
+impl WindowRule {
+    pub fn matches(&self, window: &Window) -> bool {
+        let found = |pattern: &Option<Pattern>, s: &str| match pattern {
+            Some(Pattern(pattern)) => pattern.as_ref().is_ok_and(|x| x.is_match(s)),
+            None => true,
+        };
+        (self.class.is_some() || self.title.is_some())
+            && found(&self.class, &window.class)
+            && found(&self.title, &window.title)
+    }
+}
+
+// A regular expression, compiled when the config is read. One that isn't
+// valid is reported as a problem, and doesn't match anything.
+#[derive(Clone)]
+pub struct Pattern(Result<Regex, String>);
+impl<'de> Deserialize<'de> for Pattern {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        Ok(Pattern(Regex::new(&s).map_err(|e| e.to_string())))
+    }
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(untagged)]
+pub enum KeymapConfig {
+    // Changes to the default keymap, by machine key name
+    Named(BTreeMap<String, KeyConfig>),
+    // Steno keys for each Gemini PR bit, replacing the default keymap
+    Positional(Vec<String>),
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(untagged)]
+pub enum KeyConfig {
+    // Steno keys, or "" for none
+    Keys(String),
+    // A stroke sent instead of the whole chord, like { stroke = "*" }
+    Stroke { stroke: String },
+}
+impl KeyConfig {
+    fn mapping(&self) -> Result<Mapping, String> {
+        let (mapping, s) = match self {
+            KeyConfig::Keys(s) => (Mapping::keys(s), s),
+            KeyConfig::Stroke { stroke } => (Mapping::stroke(stroke), stroke),
+        };
+        mapping.ok_or_else(|| format!("\"{}\" is not valid steno", s))
+    }
+}
+
+#[derive(Deserialize, Default, PartialEq, Clone)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputMode {
+    #[default]
+    Keyboard,
+    Clipboard,
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct Output {
+    pub mode: OutputMode,
+    // Length at which text is pasted in clipboard mode
+    pub paste_length: usize,
+}
+impl Default for Output {
+    fn default() -> Self {
+        Self {
+            mode: OutputMode::Keyboard,
+            paste_length: DEFAULT_PASTE_LENGTH,
+        }
+    }
+}
+
+// Files replacing parts of the English orthography
+#[derive(Deserialize, Default, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct Orthography {
+    pub rules: Option<String>,
+    pub words: Option<String>,
+    pub exceptions: Option<String>,
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct Drill {
+    // Passages to practise, separated by blank lines. Without a file, drills
+    // are words picked from the dictionaries.
+    pub text: Option<String>,
+    // Number of words picked from the dictionaries
+    pub words: usize,
+    // Seconds without a stroke before the next word's outlines are shown
+    pub hint_delay: f64,
+}
+impl Drill {
+    pub fn hint_delay(&self) -> Option<Duration> {
+        Duration::try_from_secs_f64(self.hint_delay).ok()
+    }
+}
+impl Default for Drill {
+    fn default() -> Self {
+        Self {
+            text: None,
+            words: 20,
+            hint_delay: 3.0,
+        }
+    }
+}
+
+// The control API for plugins and scripts, which is on by default.
+#[derive(Deserialize, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct Control {
+    pub enabled: bool,
+    // Unix socket to listen on, instead of the one in the runtime directory
+    pub socket: Option<String>,
+}
+impl Default for Control {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            socket: None,
+        }
+    }
+}
+
+// The stroke feed for overlays and other displays, which is off by default.
+#[derive(Deserialize, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct Websocket {
+    pub enabled: bool,
+    // Address to listen on, which is only reachable locally by default
+    pub address: String,
+}
+impl Default for Websocket {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            address: "127.0.0.1:8086".to_string(),
+        }
+    }
+}
+
+impl Config {
+    pub fn default_path() -> PathBuf {
+        let proj_dirs = ProjectDirs::from("", "", "moreover").unwrap();
+        proj_dirs.config_dir().join("moreover.toml")
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let s = fs::read_to_string(path)
+            .map_err(|e| format!("Could not read {}: {}", path.display(), e))?;
+        toml::from_str(&s).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    // The settings of a profile. The default profile is the top level
+    // settings, unless there's a profile with that name.
+    pub fn with_profile(&self, name: &str) -> Result<Config, String> {
+        let profile = match self.profiles.get(name) {
+            Some(profile) => profile,
+            None if name == DEFAULT_PROFILE => return Ok(self.clone()),
+            None => return Err(format!("Unknown profile: {}", name)),
+        };
+        let mut config = self.clone();
+        if let Some(dictionaries) = &profile.dictionaries {
+            config.dictionaries = dictionaries.clone();
+        }
+        if let Some(keymap) = &profile.keymap {
+            config.keymap = Some(keymap.clone());
+        }
+        if let Some(output) = &profile.output {
+            config.output = output.clone();
+        }
+        if let Some(orthography) = &profile.orthography {
+            config.orthography = orthography.clone();
+        }
+        Ok(config)
+    }
+
+    // The settings changed by a window rule.
+    pub fn with_window(&self, rule: &WindowRule) -> Config {
+        let mut config = self.clone();
+        config.dictionaries.retain(|x| !rule.disable.contains(x));
+        config
+            .dictionaries
+            .extend(rule.dictionaries.iter().cloned());
+        if let Some(output) = &rule.output {
+            config.output = output.clone();
+        }
+        config
+    }
+
+    // Everything wrong with the config, including each profile. The machine
+    // settings are only checked if they're needed.
+    pub fn problems(&self, machine: bool) -> Vec<String> {
+        let defaults = self.settings_problems(machine);
+        let mut problems = defaults.clone();
+        // Problems with settings a profile doesn't replace are only reported once
+        let mut profiles = vec![];
+        for name in self.profiles.keys() {
+            let config = self.with_profile(name).unwrap();
+            let own = config.settings_problems(machine);
+            for problem in &own {
+                if !defaults.contains(problem) {
+                    problems.push(format!("Profile {}: {}", name, problem));
+                }
+            }
+            profiles.push((name, config, own));
+        }
+        for (i, rule) in self.windows.iter().enumerate() {
+            if rule.class.is_none() && rule.title.is_none() {
+                problems.push(format!(
+                    "Window rule {}: Missing key: class or title",
+                    i + 1
+                ));
+            }
+            for Pattern(pattern) in [&rule.class, &rule.title].into_iter().flatten() {
+                if let Err(e) = pattern {
+                    problems.push(format!("Window rule {}: {}", i + 1, e));
+                }
+            }
+            let found = self.with_window(rule).settings_problems(machine);
+            for problem in &found {
+                if !defaults.contains(problem) {
+                    problems.push(format!("Window rule {}: {}", i + 1, problem));
+                }
+            }
+            // Rules change whichever profile is in use
+            for (name, config, own) in &profiles {
+                for problem in config.with_window(rule).settings_problems(machine) {
+                    if !own.contains(&problem) && !found.contains(&problem) {
+                        problems.push(format!(
+                            "Profile {}: Window rule {}: {}",
+                            name,
+                            i + 1,
+                            problem
+                        ));
+                    }
+                }
+            }
+        }
+        problems
+    }
+
+    fn settings_problems(&self, machine: bool) -> Vec<String> {
+        let mut problems = vec![];
+
+        if self.dictionaries.is_empty() {
+            problems.push("No dictionaries are configured".to_string());
+        }
+        for dict in &self.dictionaries {
+            if !dict.ends_with(".txt") {
+                problems.push(format!("Unsupported dictionary format: {}", dict));
+            } else if !Path::new(dict).is_file() {
+                problems.push(format!("Dictionary not found: {}", dict));
+            }
+        }
+        for path in [
+            &self.orthography.rules,
+            &self.orthography.words,
+            &self.orthography.exceptions,
+        ]
+        .into_iter()
+        .flatten()
+        {
+            if !Path::new(path).is_file() {
+                problems.push(format!("Orthography file not found: {}", path));
+            }
+        }
+        if self.output.paste_length == 0 {
+            problems.push("output.paste_length must be at least 1".to_string());
+        }
+        if let Some(path) = &self.drill.text {
+            if !Path::new(path).is_file() {
+                problems.push(format!("Drill text not found: {}", path));
+            }
+        }
+        if self.drill.words == 0 {
+            problems.push("drill.words must be at least 1".to_string());
+        }
+        if self.drill.hint_delay().is_none() {
+            problems.push("drill.hint_delay must be a number of seconds".to_string());
+        }
+        if self.websocket.enabled && self.websocket.address.to_socket_addrs().is_err() {
+            problems.push(format!(
+                "websocket.address must be a host and port: {}",
+                self.websocket.address
+            ));
+        }
+
+        if machine {
+            if self.machine.is_none() {
+                problems.push("Missing key: machine".to_string());
+            }
+            if let Err(e) = self.keymap() {
+                problems.extend(e);
+            }
+        }
+        problems
+    }
+
+    // The built-in keymap with the configured changes.
+    pub fn keymap(&self) -> Result<Keymap, Vec<String>> {
+        let mut problems = vec![];
+        let keymap = match &self.keymap {
+            None => Keymap::default(),
+            Some(KeymapConfig::Named(keys)) => {
+                let mut keymap = Keymap::default();
+                for (key, config) in keys {
+                    match config.mapping() {
+                        Ok(mapping) => {
+                            if let Err(e) = keymap.set(key, mapping) {
+                                problems.push(format!("keymap: {}", e));
+                            }
+                        }
+                        Err(e) => problems.push(format!("keymap: {}: {}", key, e)),
+                    }
+                }
+                keymap
+            }
+            Some(KeymapConfig::Positional(keys)) => {
+                let mappings: Vec<Mapping> = keys
+                    .iter()
+                    .filter_map(|x| {
+                        Mapping::keys(x).or_else(|| {
+                            problems.push(format!("keymap: \"{}\" is not valid steno", x));
+                            None
+                        })
+                    })
+                    .collect();
+                match mappings.try_into() {
+                    Ok(mappings) => Keymap::new(mappings),
+                    Err(_) if !problems.is_empty() => Keymap::default(),
+                    Err(_) => {
+                        problems.push(format!(
+                            "keymap has {} keys, but Gemini PR needs 42",
+                            keys.len()
+                        ));
+                        Keymap::default()
+                    }
+                }
+            }
+        };
+        if problems.is_empty() {
+            Ok(keymap)
+        } else {
+            Err(problems)
+        }
+    }
+
+    pub fn paste_length(&self) -> Option<usize> {
+        match self.output.mode {
+            OutputMode::Clipboard => Some(self.output.paste_length),
+            OutputMode::Keyboard => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dictionary() -> String {
+        format!("{}/dict/pseudo.txt", env!("CARGO_MANIFEST_DIR"))
+    }
+
+    #[test]
+    fn valid() {
+        let config: Config = toml::from_str(&format!(
+            r##"
+            dictionaries = ["{}"]
+            machine = "/dev/ttyACM0"
+            keymap = {{ "S2" = "", "#1" = {{ stroke = "*" }} }}
+            "##,
+            dictionary()
+        ))
+        .unwrap();
+        assert!(config.problems(true).is_empty());
+        assert_eq!(
+            Config::default().problems(false),
+            ["No dictionaries are configured"]
+        );
+    }
+
+    #[test]
+    fn problems() {
+        // Every problem is reported, not just the first
+        let config: Config = toml::from_str(&format!(
+            r#"
+            dictionaries = ["{}", "missing.txt", "main.json"]
+            keymap = ["S", "T", "K"]
+            output = {{ paste_length = 0 }}
+            orthography = {{ rules = "missing_rules.txt" }}
+            drill = {{ words = 0, hint_delay = -1.0 }}
+            "#,
+            dictionary()
+        ))
+        .unwrap();
+        assert_eq!(
+            config.problems(true),
+            [
+                "Dictionary not found: missing.txt",
+                "Unsupported dictionary format: main.json",
+                "Orthography file not found: missing_rules.txt",
+                "output.paste_length must be at least 1",
+                "drill.words must be at least 1",
+                "drill.hint_delay must be a number of seconds",
+                "Missing key: machine",
+                "keymap has 3 keys, but Gemini PR needs 42",
+            ]
+        );
+        // The machine settings are only checked when needed
+        assert_eq!(config.problems(false).len(), 6);
+
+        let config: Config = toml::from_str(
+            r#"
+            keymap = { "S1" = "Q", "Nope" = "S" }
+            "#,
+        )
+        .unwrap();
+        let problems = config.keymap().unwrap_err();
+        assert_eq!(problems.len(), 2);
+        assert!(problems[0].starts_with("keymap: "));
+
+        // Profiles only report their own problems
+        let config: Config = toml::from_str(&format!(
+            r#"
+            dictionaries = ["missing.txt"]
+            [profiles.other]
+            dictionaries = ["{}", "other.txt"]
+            "#,
+            dictionary()
+        ))
+        .unwrap();
+        assert_eq!(
+            config.problems(false),
+            [
+                "Dictionary not found: missing.txt",
+                "Profile other: Dictionary not found: other.txt",
+            ]
+        );
+
+        // Window rules are checked with each profile they could change. The
+        // default settings keep the dictionary by another path.
+        let config: Config = toml::from_str(&format!(
+            r#"
+            dictionaries = ["{0}", "{1}"]
+            [profiles.other]
+            dictionaries = ["{0}"]
+            [[windows]]
+            class = "Code"
+            disable = ["{0}"]
+            "#,
+            dictionary(),
+            dictionary().replace("/dict/", "/dict/./")
+        ))
+        .unwrap();
+        assert_eq!(
+            config.problems(false),
+            ["Profile other: Window rule 1: No dictionaries are configured"]
+        );
+    }
+
+    #[test]
+    fn window_rules() {
+        let config: Config = toml::from_str(
+            r#"
+            dictionaries = ["main.txt", "prose.txt"]
+            [[windows]]
+            class = "^(Code|Emacs)$"
+            dictionaries = ["code.txt"]
+            disable = ["prose.txt"]
+            output = { mode = "clipboard" }
+            [[windows]]
+            title = "vim"
+            [[windows]]
+            dictionaries = ["x.txt"]
+            [[windows]]
+            class = "("
+            "#,
+        )
+        .unwrap();
+        let window = |class: &str, title: &str| Window {
+            class: class.to_string(),
+            title: title.to_string(),
+        };
+        let rule = |window| config.windows.iter().position(|x| x.matches(&window));
+        assert_eq!(rule(window("Code", "main.rs")), Some(0));
+        assert_eq!(rule(window("Codex", "main.rs")), None);
+        assert_eq!(rule(window("kitty", "vim notes.txt")), Some(1));
+        // A rule without a class or title doesn't match anything
+        assert_eq!(rule(window("", "")), None);
+
+        let code = config.with_window(&config.windows[0]);
+        assert_eq!(code.dictionaries, ["main.txt", "code.txt"]);
+        assert!(code.paste_length().is_some());
+        let problems = config.problems(false);
+        assert!(problems.contains(&"Window rule 1: Dictionary not found: code.txt".to_string()));
+        assert!(problems.contains(&"Window rule 3: Missing key: class or title".to_string()));
+        // Invalid patterns are reported, and don't match
+        assert!(problems
+            .iter()
+            .any(|x| x.starts_with("Window rule 4: regex parse error")));
+        assert_eq!(rule(window("(", "")), None);
+    }
+}
@@ -0,0 +1,435 @@
+/*
+    Control API for editor plugins and scripts, over a Unix socket. Requests
+    and responses are JSON objects, one per line. Requests have a "command",
+    and an "id" that's given back with the response. Connections that
+    subscribe are also sent the engine's events, with an "event" instead.
+*/
+
+use crate::config::DEFAULT_PROFILE;
+use crate::event::{event, millis};
+use crate::lookup;
+use crate::Input;
+use crate::Ui;
+use directories::ProjectDirs;
+use moreover::buffer::Transcript;
+use moreover::{engine, Engine};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::fs;
+use std::io::{self, BufRead, BufReader, Write};
+use std::os::unix::fs::{DirBuilderExt, PermissionsExt};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+
+pub fn default_path() -> PathBuf {
+    let proj_dirs = ProjectDirs::from("", "", "moreover").unwrap();
+    proj_dirs
+        .runtime_dir()
+        .unwrap_or(proj_dirs.data_dir())
+        .join("moreover.sock")
+}
+
+#[derive(Deserialize)]
+struct Message {
+    #[serde(default)]
+    id: Value,
+    #[serde(flatten)]
+    request: Request,
+}
+
+#[derive(Deserialize, Debug, PartialEq)]
+#[serde(tag = "command", rename_all = "snake_case")]
+enum Request {
+    // Be sent events from now on
+    Subscribe,
+    // Translations of an outline
+    Lookup {
+        outline: String,
+    },
+    // Outlines for some text
+    ReverseLookup {
+        text: String,
+    },
+    // Add an entry to the named dictionary, or the highest priority one
+    // that's on and can be written to
+    Add {
+        outline: String,
+        translation: String,
+        dictionary: Option<String>,
+    },
+    // Turn output on or off, or toggle it
+    Output {
+        enabled: Option<bool>,
+    },
+    Profile {
+        name: String,
+    },
+    // Strokes processed as if they came from the machine
+    Stroke {
+        outline: String,
+    },
+    // The loaded dictionaries, highest priority first
+    Dictionaries,
+    // Turn a dictionary on or off, or move it to a priority, where 0 is the
+    // highest
+    Dictionary {
+        // The dictionary's own id, not the request's
+        dictionary_id: usize,
+        enabled: Option<bool>,
+        priority: Option<usize>,
+    },
+    // The text written so far, and optionally each word with when it was
+    // written
+    Transcript {
+        #[serde(default)]
+        words: bool,
+    },
+}
+
+struct Server {
+    engine: Arc<Mutex<Engine>>,
+    transcript: Arc<Mutex<Transcript>>,
+    input: mpsc::Sender<Input>,
+    ui: mpsc::Sender<Ui>,
+    profiles: Vec<String>,
+    subscribers: Mutex<Vec<mpsc::Sender<String>>>,
+}
+impl Server {
+    fn handle(&self, request: Request, connection: &mpsc::Sender<String>) -> Result<Value, String> {
+        match request {
+            Request::Subscribe => {
+                self.subscribers.lock().unwrap().push(connection.clone());
+                Ok(Value::Null)
+            }
+            Request::Lookup { outline } => {
+                let outline = parse_outline(&outline)?;
+                let rows = lookup::translations(&self.engine.lock().unwrap(), &outline);
+                let used = rows.iter().find(|x| x.used);
+                let overridden: Vec<Value> = rows
+                    .iter()
+                    .filter(|x| !x.used && x.translation.is_some())
+                    .map(|x| json!({"dictionary": x.name, "translation": x.translation}))
+                    .collect();
+                Ok(json!({
+                    "translation": used.and_then(|x| x.translation.clone()),
+                    "dictionary": used.map(|x| x.name.clone()),
+                    "overridden": overridden,
+                }))
+            }
+            Request::ReverseLookup { text } => {
+                let outlines = lookup::outlines(&self.engine.lock().unwrap(), &text);
+                Ok(outlines
+                    .into_iter()
+                    .map(|(outline, dictionary)| json!({"outline": outline, "dictionary": dictionary}))
+                    .collect())
+            }
+            Request::Add {
+                outline,
+                translation,
+                dictionary,
+            } => {
+                let outline = parse_outline(&outline)?;
+                let mut engine = self.engine.lock().unwrap();
+                let i = engine
+                    .dictionaries()
+                    .position(|x| match &dictionary {
+                        Some(name) => x.name() == name,
+                        None => x.is_enabled() && x.is_writable(),
+                    })
+                    .ok_or_else(|| match &dictionary {
+                        Some(name) => format!("No dictionary named {}", name),
+                        None => "No dictionary can be written to".to_string(),
+                    })?;
+                lookup::set(&mut engine, i, &outline, Some(&translation))?;
+                let name = engine.dictionaries().nth(i).unwrap().name().to_string();
+                Ok(json!({ "dictionary": name }))
+            }
+            Request::Output { enabled } => {
+                let mut engine = self.engine.lock().unwrap();
+                let enabled = enabled.unwrap_or(!engine.is_enabled());
+                if enabled != engine.is_enabled() {
+                    engine.set_enabled(enabled);
+                    // Output is already changed, even if the display has exited
+                    let _ = self.ui.send(Ui::Output(enabled));
+                }
+                Ok(json!({ "enabled": enabled }))
+            }
+            Request::Profile { name } => {
+                if name != DEFAULT_PROFILE && !self.profiles.contains(&name) {
+                    return Err(format!("Unknown profile: {}", name));
+                }
+                self.send(Input::Command(format!("profile:{}", name)))?;
+                Ok(Value::Null)
+            }
+            Request::Stroke { outline } => {
+                for stroke in parse_outline(&outline)? {
+                    self.send(Input::Stroke(stroke))?;
+                }
+                Ok(Value::Null)
+            }
+            Request::Dictionaries => Ok(dictionaries(&self.engine.lock().unwrap())),
+            Request::Dictionary {
+                dictionary_id: id,
+                enabled,
+                priority,
+            } => {
+                let mut engine = self.engine.lock().unwrap();
+                if !engine.dictionaries().any(|x| x.id() == id) {
+                    return Err(format!("No dictionary with id {}", id));
+                }
+                if let Some(enabled) = enabled {
+                    engine.set_dictionary_enabled(id, enabled);
+                }
+                if let Some(priority) = priority {
+                    engine.set_dictionary_priority(id, priority);
+                }
+                Ok(dictionaries(&engine))
+            }
+            Request::Transcript { words } => {
+                let transcript = self.transcript.lock().unwrap();
+                let mut result = json!({ "text": transcript.text() });
+                if words {
+                    result["words"] = transcript
+                        .words()
+                        .iter()
+                        .map(|x| json!({"word": x.text, "start": millis(x.start), "end": millis(x.end)}))
+                        .collect();
+                }
+                Ok(result)
+            }
+        }
+    }
+
+    fn send(&self, input: Input) -> Result<(), String> {
+        self.input
+            .send(input)
+            .map_err(|_| "The engine isn't running".to_string())
+    }
+
+    fn publish(&self, msg: &Ui) {
+        if let Some(event) = event(msg) {
+            let line = event.to_string();
+            self.subscribers
+                .lock()
+                .unwrap()
+                .retain(|x| x.send(line.clone()).is_ok());
+        }
+    }
+}
+
+fn parse_outline(s: &str) -> Result<Vec<u32>, String> {
+    engine::parse_outline(s)
+        .filter(|x| !x.is_empty())
+        .ok_or_else(|| format!("\"{}\" is not valid steno", s))
+}
+
+fn dictionaries(engine: &Engine) -> Value {
+    engine
+        .dictionaries()
+        .enumerate()
+        .map(|(i, x)| {
+            json!({
+                "id": x.id(),
+                "name": x.name(),
+                "enabled": x.is_enabled(),
+                "priority": i,
+                "writable": x.is_writable(),
+            })
+        })
+        .collect()
+}
+
+// Listen on the socket, returning where to send the messages that
+// subscribers are sent events for. A socket left behind by a moreover that
+// isn't running anymore is replaced.
+pub fn start(
+    path: &Path,
+    engine: Arc<Mutex<Engine>>,
+    transcript: Arc<Mutex<Transcript>>,
+    input: mpsc::Sender<Input>,
+    ui: mpsc::Sender<Ui>,
+    profiles: Vec<String>,
+) -> io::Result<mpsc::Sender<Ui>> {
+    if path.exists() {
+        if UnixStream::connect(path).is_ok() {
+            return Err(io::Error::new(
+                io::ErrorKind::AddrInUse,
+                format!("{} is in use by another moreover", path.display()),
+            ));
+        }
+        fs::remove_file(path)?;
+    }
+    let dir = path.parent().unwrap_or(Path::new("."));
+    fs::create_dir_all(dir)?;
+    // Anyone who can connect can type as the user, so the socket is made in a
+    // directory only the user can get into, and only moved into place once
+    // no one else can connect to it
+    let private = dir.join(format!(".moreover-{}", std::process::id()));
+    fs::DirBuilder::new().mode(0o700).create(&private)?;
+    let temp = private.join("socket");
+    let listener = UnixListener::bind(&temp).and_then(|listener| {
+        fs::set_permissions(&temp, fs::Permissions::from_mode(0o600))?;
+        fs::rename(&temp, path)?;
+        Ok(listener)
+    });
+    let _ = fs::remove_file(&temp);
+    fs::remove_dir(&private)?;
+    let listener = listener?;
+
+    let server = Arc::new(Server {
+        engine,
+        transcript,
+        input,
+        ui,
+        profiles,
+        subscribers: Mutex::new(vec![]),
+    });
+    let (tx, rx) = mpsc::channel::<Ui>();
+    let publisher = server.clone();
+    thread::spawn(move || {
+        for msg in rx {
+            publisher.publish(&msg);
+        }
+    });
+    thread::spawn(move || {
+        for stream in listener.incoming().map_while(Result::ok) {
+            let server = server.clone();
+            thread::spawn(move || serve(stream, &server));
+        }
+    });
+    Ok(tx)
+}
+
+// Answer requests until the connection is closed. Responses and events are
+// written by a thread of their own, so a slow reader doesn't hold up the engine.
+fn serve(stream: UnixStream, server: &Server) -> io::Result<()> {
+    let (tx, rx) = mpsc::channel::<String>();
+    let mut writer = stream.try_clone()?;
+    thread::spawn(move || {
+        for line in rx {
+            if writeln!(writer, "{}", line).is_err() {
+                break;
+            }
+        }
+    });
+    for line in BufReader::new(stream).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let response = match serde_json::from_str::<Message>(&line) {
+            Ok(msg) => match server.handle(msg.request, &tx) {
+                Ok(result) => json!({ "id": msg.id, "result": result }),
+                Err(e) => json!({ "id": msg.id, "error": e }),
+            },
+            Err(e) => json!({ "id": null, "error": e.to_string() }),
+        };
+        if tx.send(response.to_string()).is_err() {
+            break;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use moreover::engine::dictionary::{Dictionary, TreeDict};
+    use moreover::engine::steno_to_id;
+    use std::time::UNIX_EPOCH;
+
+    #[test]
+    fn requests() {
+        let mut engine = Engine::new();
+        let dict: Box<dyn Dictionary + Send> =
+            Box::new(TreeDict::from_reader("KAt\tcat\n".as_bytes()));
+        engine.add_dictionary(dict);
+        let (input, input_rx) = mpsc::channel();
+        let (ui, ui_rx) = mpsc::channel();
+        let server = Server {
+            engine: Arc::new(Mutex::new(engine)),
+            transcript: Arc::default(),
+            input,
+            ui,
+            profiles: vec!["coding".to_string()],
+            subscribers: Mutex::new(vec![]),
+        };
+        let (connection, events) = mpsc::channel();
+        let request = |s: &str| {
+            let msg: Message = serde_json::from_str(s).unwrap();
+            server.handle(msg.request, &connection)
+        };
+
+        assert_eq!(
+            request(r#"{"command": "lookup", "outline": "KAt"}"#).unwrap()["translation"],
+            "cat"
+        );
+        assert_eq!(
+            request(r#"{"command": "reverse_lookup", "text": "cat"}"#).unwrap()[0]["outline"],
+            "KAt"
+        );
+        // The dictionary wasn't read from a file
+        assert!(request(r#"{"command": "add", "outline": "KAT", "translation": "cat"}"#).is_err());
+        assert!(request(r#"{"command": "lookup", "outline": "KA!"}"#).is_err());
+
+        request(r#"{"command": "output"}"#).unwrap();
+        assert!(matches!(ui_rx.try_recv(), Ok(Ui::Output(false))));
+        assert!(!server.engine.lock().unwrap().is_enabled());
+
+        request(r#"{"command": "stroke", "outline": "KAt/s"}"#).unwrap();
+        request(r#"{"command": "profile", "name": "coding"}"#).unwrap();
+        assert!(request(r#"{"command": "profile", "name": "prose"}"#).is_err());
+        let inputs: Vec<Input> = input_rx.try_iter().collect();
+        assert_eq!(
+            inputs,
+            vec![
+                Input::Stroke(steno_to_id("KAt")),
+                Input::Stroke(steno_to_id("s")),
+                Input::Command("profile:coding".to_string())
+            ]
+        );
+
+        request(r#"{"command": "subscribe"}"#).unwrap();
+        server.publish(&Ui::Output(true));
+        server.publish(&Ui::Tick);
+        let events: Vec<String> = events.try_iter().collect();
+        assert_eq!(events, vec![r#"{"enabled":true,"event":"output"}"#]);
+
+        let numbers = request(r#"{"command": "dictionaries"}"#).unwrap()[1].clone();
+        assert_eq!(numbers["name"], "numbers");
+        let request_json = |x: Value| request(&x.to_string());
+        let changed = request_json(
+            json!({"command": "dictionary", "dictionary_id": numbers["id"], "enabled": false, "priority": 0}),
+        )
+        .unwrap();
+        assert_eq!(
+            (
+                &changed[0]["name"],
+                &changed[0]["enabled"],
+                &changed[0]["priority"]
+            ),
+            (&json!("numbers"), &json!(false), &json!(0))
+        );
+        assert!(
+            request(r#"{"command": "dictionary", "dictionary_id": 100, "enabled": true}"#).is_err()
+        );
+
+        let time = UNIX_EPOCH + std::time::Duration::from_secs(1);
+        let text = |s: &str| vec![moreover::Action::Text(s.to_string())];
+        server
+            .transcript
+            .lock()
+            .unwrap()
+            .apply(time, &[], &text("The cat"));
+        assert_eq!(
+            request(r#"{"command": "transcript"}"#).unwrap(),
+            json!({ "text": "The cat" })
+        );
+        assert_eq!(
+            request(r#"{"command": "transcript", "words": true}"#).unwrap()["words"][1],
+            json!({ "word": "cat", "start": 1000, "end": 1000 })
+        );
+    }
+}
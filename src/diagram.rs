@@ -0,0 +1,92 @@
+/*
+    Steno keyboard diagram, laid out like a machine with two banks of keys
+    and the vowels under the thumbs.
+*/
+
+use moreover::engine::STENO_ORDER;
+
+// Keys of the standard layout by row, in the notation of the steno order.
+// Keys that span rows, like S and *, are in each of them.
+const LAYOUT: [&str; 4] = ["##########", "STPH*fpltd", "SKWR*rbgsz", "  AO eu   "];
+
+// A key's label and whether it's pressed.
+pub type Key = Option<(char, bool)>;
+
+// Rows of keys, with None for gaps. Keys in the steno order that the layout
+// doesn't have are put in a row of their own at the top, and keys of the
+// layout that aren't in the steno order are left out.
+pub fn rows(stroke: u32) -> Vec<Vec<Key>> {
+    let key = |c: char| {
+        STENO_ORDER
+            .find(c)
+            .map(|i| (c.to_ascii_uppercase(), stroke & (1 << i) != 0))
+    };
+
+    let mut rows = vec![];
+    let extra: Vec<Key> = STENO_ORDER
+        .chars()
+        .filter(|c| !LAYOUT.iter().any(|row| row.contains(*c)))
+        .map(key)
+        .collect();
+    if !extra.is_empty() {
+        rows.push(extra);
+    }
+    for row in LAYOUT {
+        rows.push(
+            row.chars()
+                .map(|c| if c == ' ' { None } else { key(c) })
+                .collect(),
+        );
+    }
+    rows
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use moreover::engine::steno_to_id;
+
+    // Each row's labels, with gaps as spaces and pressed keys in brackets.
+    fn render(rows: &[Vec<Key>]) -> Vec<String> {
+        rows.iter()
+            .map(|row| {
+                row.iter()
+                    .map(|key| match key {
+                        Some((c, true)) => format!("[{}]", c),
+                        Some((c, false)) => c.to_string(),
+                        None => " ".to_string(),
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn layout() {
+        assert_eq!(
+            render(&rows(steno_to_id("STPHfplt"))),
+            [
+                "^+",
+                "##########",
+                "[S][T][P][H]*[F][P][L][T]D",
+                "[S]KWR*RBGSZ",
+                "  AO EU   ",
+            ]
+        );
+    }
+
+    #[test]
+    fn pressed() {
+        let rows = rows(steno_to_id("#SAt"));
+        let pressed: Vec<Vec<char>> = rows
+            .iter()
+            .map(|row| row.iter().flatten().filter(|x| x.1).map(|x| x.0).collect())
+            .collect();
+        assert_eq!(
+            pressed,
+            vec![vec![], vec!['#'; 10], vec!['S', 'T'], vec!['S'], vec!['A']]
+        );
+        // The keys that aren't in the standard layout
+        assert_eq!(rows[0], vec![Some(('^', false)), Some(('+', false))]);
+    }
+}
@@ -0,0 +1,253 @@
+/*
+    Practice drills: a target passage is compared with what's written, word by
+    word, as the strokes come in. Drills are passages from a file, or words
+    picked from the dictionaries.
+*/
+
+use moreover::Engine;
+use std::collections::{BTreeSet, HashSet};
+use std::time::{Duration, SystemTime};
+
+// A longer pause between strokes is a hesitation.
+const HESITATION: Duration = Duration::from_secs(2);
+
+pub struct Drill {
+    target: Vec<String>,
+    text: String,
+    // When the drill was shown, which hints wait from until there's a stroke
+    shown: SystemTime,
+    start: Option<SystemTime>,
+    last: Option<SystemTime>,
+    // Target words paused on, in order
+    hesitations: Vec<usize>,
+    // Target words that were written wrong before moving on, even if they
+    // were corrected later
+    mistakes: BTreeSet<usize>,
+}
+impl Drill {
+    pub fn new(target: &str, shown: SystemTime) -> Self {
+        Self {
+            target: target.split_whitespace().map(String::from).collect(),
+            text: String::new(),
+            shown,
+            start: None,
+            last: None,
+            hesitations: vec![],
+            mistakes: BTreeSet::new(),
+        }
+    }
+
+    // Add the text a stroke took back and typed.
+    pub fn add(&mut self, time: SystemTime, deleted: &str, added: &str) {
+        if self.is_done() {
+            return;
+        }
+        if let Some(last) = self.last {
+            let gap = time.duration_since(last).unwrap_or_default();
+            if gap > HESITATION && self.current() < self.target.len() {
+                self.hesitations.push(self.current());
+            }
+        }
+        self.start.get_or_insert(time);
+        self.last = Some(time);
+
+        for _ in deleted.chars() {
+            self.text.pop();
+        }
+        self.text.push_str(added);
+
+        let written = self.written();
+        let mistakes: Vec<usize> = (0..written.len().saturating_sub(1).min(self.target.len()))
+            .filter(|x| written[*x] != self.target[*x])
+            .collect();
+        self.mistakes.extend(mistakes);
+    }
+
+    pub fn target(&self) -> &[String] {
+        &self.target
+    }
+
+    pub fn written(&self) -> Vec<&str> {
+        self.text.split_whitespace().collect()
+    }
+
+    // Index of the target word being written. A word that doesn't match its
+    // target yet is still being written, since it could be a prefix or be
+    // missing a suffix.
+    pub fn current(&self) -> usize {
+        let written = self.written();
+        match written.last() {
+            Some(word) if Some(*word) != self.target.get(written.len() - 1).map(|x| x.as_str()) => {
+                written.len() - 1
+            }
+            _ => written.len(),
+        }
+    }
+
+    // Whether the word at the index was written as the target has it.
+    pub fn is_correct(&self, i: usize) -> Option<bool> {
+        let written = self.written();
+        let word = written.get(i)?;
+        Some(self.target.get(i).is_some_and(|x| x == word))
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.current() >= self.target.len()
+    }
+
+    // Fraction of the words written so far that were right the first time.
+    pub fn accuracy(&self) -> f64 {
+        let n = self.current().min(self.target.len());
+        if n == 0 {
+            return 1.0;
+        }
+        let mistakes = self.mistakes.range(..n).count();
+        (n - mistakes) as f64 / n as f64
+    }
+
+    // Correct words per minute, from the first stroke to the last.
+    pub fn wpm(&self) -> f64 {
+        let time = match (self.start, self.last) {
+            (Some(start), Some(last)) => last.duration_since(start).unwrap_or_default(),
+            _ => return 0.0,
+        };
+        if time.is_zero() {
+            return 0.0;
+        }
+        let correct = (0..self.written().len())
+            .filter(|x| self.is_correct(*x) == Some(true))
+            .count();
+        correct as f64 / (time.as_secs_f64() / 60.0)
+    }
+
+    pub fn hesitations(&self) -> Vec<&str> {
+        self.hesitations
+            .iter()
+            .map(|x| self.target[*x].as_str())
+            .collect()
+    }
+
+    // Whether the target word at the index was paused on or written wrong.
+    pub fn struggled(&self, i: usize) -> bool {
+        self.hesitations.contains(&i) || self.mistakes.contains(&i)
+    }
+
+    // The word to show a hint for, once there's been no stroke for the delay.
+    pub fn hint(&self, now: SystemTime, delay: Duration) -> Option<&str> {
+        if self.is_done() {
+            return None;
+        }
+        let waiting = now
+            .duration_since(self.last.unwrap_or(self.shown))
+            .unwrap_or_default();
+        if waiting < delay {
+            return None;
+        }
+        self.target.get(self.current()).map(|x| x.as_str())
+    }
+}
+
+// Words that the dictionaries that are on have a translation for, without any
+// formatting.
+pub fn dictionary_words(engine: &Engine) -> Vec<String> {
+    let mut seen = HashSet::new();
+    let mut words = vec![];
+    for dict in engine.dictionaries().filter(|x| x.is_enabled()) {
+        for (_, raw) in dict.entries() {
+            let is_word = raw.chars().all(|c| c.is_alphabetic() || c == '\'');
+            if !raw.is_empty() && is_word && seen.insert(raw.clone()) {
+                words.push(raw);
+            }
+        }
+    }
+    words.sort();
+    words
+}
+
+// Pick n of the items, without repeats while there are enough of them.
+pub fn pick<T: Clone>(items: &[T], n: usize, seed: u64) -> Vec<T> {
+    let mut rng = Rng(seed | 1);
+    let mut items = items.to_vec();
+    let mut picked = vec![];
+    for i in 0..n.min(items.len()) {
+        let j = i + rng.next() as usize % (items.len() - i);
+        items.swap(i, j);
+        picked.push(items[i].clone());
+    }
+    picked
+}
+
+// Passages in a file, separated by blank lines.
+pub fn passages(text: &str) -> Vec<String> {
+    let mut passages = vec![];
+    let mut passage: Vec<&str> = vec![];
+    for line in text.lines().chain([""]) {
+        if line.trim().is_empty() {
+            if !passage.is_empty() {
+                passages.push(passage.join(" "));
+                passage.clear();
+            }
+        } else {
+            passage.extend(line.split_whitespace());
+        }
+    }
+    passages
+}
+
+// Xorshift, which is random enough to vary drills.
+struct Rng(u64);
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn drill() {
+        let at = |s| SystemTime::UNIX_EPOCH + Duration::from_secs(s);
+        let mut drill = Drill::new("the cats sat", at(0));
+        assert_eq!(drill.hint(at(0), Duration::from_secs(3)), None);
+        assert_eq!(drill.hint(at(3), Duration::from_secs(3)), Some("the"));
+
+        drill.add(at(0), "", " the");
+        drill.add(at(1), "", " cat");
+        // Still writing cats
+        assert_eq!(drill.current(), 1);
+        assert_eq!(drill.hint(at(2), Duration::from_secs(3)), None);
+        assert_eq!(drill.hint(at(4), Duration::from_secs(3)), Some("cats"));
+        // A wrong word that's moved on from is a mistake, even once it's fixed
+        drill.add(at(2), "", " sad");
+        drill.add(at(3), " sad", "");
+        drill.add(at(4), " cat", " cats");
+        assert!((drill.accuracy() - 0.5).abs() < 1e-9);
+        drill.add(at(8), "", " sad");
+        assert!(!drill.is_done());
+        assert_eq!(drill.is_correct(2), Some(false));
+        drill.add(at(9), " sad", " sat");
+        assert!(drill.is_done());
+        assert!((drill.accuracy() - 2.0 / 3.0).abs() < 1e-9);
+        // 3 correct words in 9 seconds
+        assert!((drill.wpm() - 20.0).abs() < 1e-9);
+        assert_eq!(drill.hesitations(), ["sat"]);
+        assert_eq!(
+            (0..3).map(|x| drill.struggled(x)).collect::<Vec<_>>(),
+            [false, true, true]
+        );
+
+        // Strokes after the end don't count
+        drill.add(at(10), " sat", "");
+        assert!(drill.is_done());
+
+        assert_eq!(passages("a\nb\n\n\n c \n"), vec!["a b", "c"]);
+        let mut picked = pick(&[1, 2, 3], 3, 42);
+        picked.sort();
+        assert_eq!(picked, vec![1, 2, 3]);
+    }
+}
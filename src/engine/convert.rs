@@ -0,0 +1,243 @@
+/*
+    Conversion from Plover's JSON dictionaries to the tab-indented tree format.
+*/
+
+use super::{id_to_compact_steno, parse_steno};
+use lazy_static::lazy_static;
+use regex::{Captures, Regex};
+use std::collections::BTreeMap;
+use std::error::Error;
+
+lazy_static! {
+    static ref CARRY_CAPS: Regex = Regex::new(r"\{(\^)?~\|(.+?)(\^)?\}").unwrap();
+}
+
+// Pseudo-steno used in converted dictionaries, in the order it's applied.
+// Keys can only be used by one replacement.
+const PSEUDO: [(&str, &str); 26] = [
+    ("STKPW", "Z"),
+    ("TKPW", "G"),
+    ("SKWR", "J"),
+    ("TPH", "N"),
+    ("KWR", "Y"),
+    ("SR", "V"),
+    ("TK", "D"),
+    ("PW", "B"),
+    ("HR", "L"),
+    ("TP", "F"),
+    ("PH", "M"),
+    // Ae, Au, Ai, Oe, Ou, Oi all make sense already
+    ("AOeu", "ii"),
+    ("AOe", "ee"),
+    ("AOu", "uu"),
+    ("AO", "oo"),
+    ("eu", "i"),
+    ("frpb", "nch"),
+    ("pblg", "j"),
+    ("frp", "mp"),
+    ("frb", "rv"),
+    ("pb", "n"),
+    ("pl", "m"),
+    ("bg", "k"),
+    ("gs", "tion"),
+    ("fp", "ch"),
+    ("rb", "sh"),
+];
+
+#[derive(Default)]
+struct Node {
+    translation: Option<String>,
+    children: BTreeMap<String, Node>,
+}
+
+// Convert a Plover JSON dictionary. Also returns the number of entries that
+// had to be skipped because their strokes use keys that don't exist here.
+pub fn from_plover_json(json: &str) -> Result<(String, usize), Box<dyn Error>> {
+    let entries: BTreeMap<String, String> = serde_json::from_str(json)?;
+
+    let mut converted = vec![];
+    let mut skipped = 0;
+    for (outline, translation) in entries {
+        let strokes: Option<Vec<u32>> = outline
+            .split('/')
+            .map(|x| parse_steno(&normalize(x)))
+            .collect();
+        match strokes {
+            Some(strokes) => converted.push((strokes, convert_translation(&translation))),
+            None => skipped += 1,
+        }
+    }
+    Ok((to_tree(converted), skipped))
+}
+
+// Write entries in the tree format, with strokes in pseudo-steno.
+pub fn to_tree(entries: impl IntoIterator<Item = (Vec<u32>, String)>) -> String {
+    let mut root = Node::default();
+    for (outline, translation) in entries {
+        let mut node = &mut root;
+        for stroke in outline {
+            node = node
+                .children
+                .entry(to_pseudo(&id_to_compact_steno(stroke)))
+                .or_default();
+        }
+        node.translation = Some(translation);
+    }
+
+    let mut output = String::new();
+    for (stroke, node) in &root.children {
+        render(&mut output, stroke, node, 0);
+    }
+    output
+}
+
+fn render(output: &mut String, stroke: &str, node: &Node, depth: usize) {
+    output.push_str(&"\t".repeat(depth));
+    output.push_str(stroke);
+    if let Some(t) = &node.translation {
+        output.push('\t');
+        output.push_str(t);
+    }
+    output.push('\n');
+    for (stroke, child) in &node.children {
+        render(output, stroke, child, depth + 1);
+    }
+}
+
+// Escape characters that can't be in the tree format, and split up
+// carried capitalization, which is only supported as its own command.
+fn convert_translation(s: &str) -> String {
+    let escaped = s.replace('\n', "\\n").replace('\t', "\\t");
+    CARRY_CAPS
+        .replace_all(&escaped, |caps: &Captures| {
+            let mut new = String::new();
+            if caps.get(1).is_some() {
+                new.push_str("{^}");
+            }
+            new.push_str("{~|}");
+            new.push_str(&caps[2]);
+            if caps.get(3).is_some() {
+                new.push_str("{^}");
+            }
+            new
+        })
+        .to_string()
+}
+
+// Convert a Plover stroke like "STKPW-RB" or "1-9" to the steno order used here,
+// with lowercase vowels and right bank keys.
+fn normalize(stroke: &str) -> String {
+    let mut left = String::new();
+    let mut middle = String::new();
+    let mut right = String::new();
+    let mut number = false;
+    let mut is_right = false;
+
+    for c in stroke.chars() {
+        let key = match c {
+            '0' => 'O',
+            '1' => 'S',
+            '2' => 'T',
+            '3' => 'P',
+            '4' => 'H',
+            '5' => 'A',
+            '6' => 'F',
+            '7' => 'P',
+            '8' => 'L',
+            '9' => 'T',
+            c => c,
+        };
+        if key != c {
+            number = true;
+            // Only numbers on the right bank are higher than 5
+            if c > '5' {
+                is_right = true;
+            }
+        }
+
+        match key {
+            'A' | 'O' | '*' => {
+                is_right = true;
+                middle.push(key);
+            }
+            'E' | 'U' => {
+                is_right = true;
+                middle.push(key.to_ascii_lowercase());
+            }
+            '-' => is_right = true,
+            '#' => number = true,
+            _ if is_right => right.push(key.to_ascii_lowercase()),
+            _ => left.push(key),
+        }
+    }
+
+    let mut s = String::new();
+    if number {
+        s.push('#');
+    }
+    s + &left + &middle + &right
+}
+
+fn to_pseudo(stroke: &str) -> String {
+    let mut stroke = stroke.to_string();
+    let mut used = String::new();
+    for (s, p) in PSEUDO {
+        if s.chars().any(|x| used.contains(x)) {
+            continue;
+        }
+        let new = stroke.replace(s, p);
+        if new != stroke {
+            stroke = new;
+            used.push_str(s);
+        }
+    }
+    stroke
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strokes() {
+        for (plover, expected) in [
+            ("STKPW-RB", "STKPWrb"),
+            ("KAT", "KAt"),
+            ("TEFT", "Teft"),
+            ("-G", "g"),
+            ("A*EU", "A*eu"),
+            ("1-9", "#St"),
+            ("#S", "#S"),
+            ("50", "#AO"),
+            ("6", "#f"),
+        ] {
+            assert_eq!(normalize(plover), expected);
+        }
+    }
+
+    #[test]
+    fn pseudo_round_trip() {
+        for stroke in [
+            "STKPWrb",
+            "TPHAOeufrpblg",
+            "SKWRAOeupblgtsdz",
+            "KWRAOu",
+            "Aeu",
+            "Hfrpl",
+        ] {
+            assert_eq!(parse_steno(&to_pseudo(stroke)), parse_steno(stroke));
+        }
+    }
+
+    #[test]
+    fn dictionary() {
+        let json =
+            r#"{"KAT": "cat", "KAT/-S": "cats", "TPHU/HRAOEUPB": "{^\n^}", "KPA*": "{^~|\"^}"}"#;
+        let (output, skipped) = from_plover_json(json).unwrap();
+        assert_eq!(skipped, 0);
+        assert_eq!(
+            output,
+            "KAt\tcat\n\ts\tcats\nKPA*\t{^}{~|}\"{^}\nNu\n\tLiin\t{^\\n^}\n"
+        );
+    }
+}
@@ -1,9 +1,11 @@
-use super::{steno_to_id, Translation};
+use super::{convert, steno_to_id, Translation};
 use lazy_static::lazy_static;
 use regex::Regex;
 use std::collections::HashMap;
-use std::fs::File;
+use std::fs::{self, File};
 use std::io::{self, BufRead};
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
 
 lazy_static! {
     static ref NUMBERS_STROKE: Regex = Regex::new(r"^#[STPHAOfpltdz]+$").unwrap();
@@ -16,7 +18,7 @@ struct Node {
 impl Node {
     fn new(t: &str, d: usize) -> Self {
         Node {
-            translation: if t.len() > 0 {
+            translation: if !t.is_empty() {
                 Some(Translation::new(t.to_string(), d))
             } else {
                 None
@@ -31,10 +33,100 @@ impl Node {
 
 pub trait Dictionary {
     fn get(&self, strokes: &[u32]) -> Option<Translation>;
+
+    // Name shown as the source of its translations.
+    fn name(&self) -> &str {
+        ""
+    }
+
+    // Every outline and its translation, for dictionaries that can list them.
+    fn entries(&self) -> Vec<(Vec<u32>, String)> {
+        vec![]
+    }
+
+    // Find every outline whose translation is exactly the given text.
+    fn reverse_lookup(&self, text: &str) -> Vec<Vec<u32>> {
+        self.entries()
+            .into_iter()
+            .filter(|(_, raw)| raw == text)
+            .map(|(outline, _)| outline)
+            .collect()
+    }
+
+    // Whether entries can be changed with set.
+    fn is_writable(&self) -> bool {
+        false
+    }
+    // Change the translation of an outline, or remove it if the translation
+    // is None, and save the dictionary.
+    fn set(&mut self, _outline: &[u32], _translation: Option<&str>) -> io::Result<()> {
+        Err(read_only())
+    }
+}
+
+// A dictionary the engine has loaded, which can be turned off or moved to
+// another priority without reading it again.
+pub struct LoadedDictionary {
+    id: usize,
+    path: String,
+    enabled: bool,
+    dictionary: Box<dyn Dictionary + Send>,
+}
+impl LoadedDictionary {
+    pub(super) fn new(id: usize, path: &str, dictionary: Box<dyn Dictionary + Send>) -> Self {
+        Self {
+            id,
+            path: path.to_string(),
+            enabled: true,
+            dictionary,
+        }
+    }
+
+    // Stays the same while the engine has the dictionary loaded, including
+    // when the settings stop using it and start again.
+    pub fn id(&self) -> usize {
+        self.id
+    }
+
+    // Where the dictionary was loaded from, which settings refer to it by, or
+    // empty if it wasn't loaded from anywhere.
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+    pub(super) fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    pub(super) fn dictionary_mut(&mut self) -> &mut (dyn Dictionary + Send) {
+        self.dictionary.as_mut()
+    }
+}
+impl Deref for LoadedDictionary {
+    type Target = dyn Dictionary + Send;
+
+    fn deref(&self) -> &Self::Target {
+        self.dictionary.as_ref()
+    }
+}
+
+fn read_only() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::PermissionDenied,
+        "dictionary can't be changed",
+    )
 }
 
 pub struct TreeDict {
     root: Node,
+    // Outlines of each translation, in order, for reverse lookups
+    reverse: HashMap<String, Vec<Vec<u32>>>,
+    name: String,
+    // File that changes are saved to
+    path: Option<PathBuf>,
 }
 impl Dictionary for TreeDict {
     fn get(&self, strokes: &[u32]) -> Option<Translation> {
@@ -46,7 +138,7 @@ impl Dictionary for TreeDict {
                 if let Some(t) = &n.translation {
                     trans = Some(t);
                 }
-                parent = &n;
+                parent = n;
             } else {
                 break;
             }
@@ -59,38 +151,146 @@ impl Dictionary for TreeDict {
         }
         None
     }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn entries(&self) -> Vec<(Vec<u32>, String)> {
+        let mut entries = vec![];
+        let mut stack = vec![(&self.root, vec![])];
+        while let Some((node, outline)) = stack.pop() {
+            if let Some(t) = &node.translation {
+                entries.push((outline.clone(), t.raw.clone()));
+            }
+            for (stroke, child) in &node.children {
+                let mut o = outline.clone();
+                o.push(*stroke);
+                stack.push((child, o));
+            }
+        }
+        entries.sort();
+        entries
+    }
+
+    fn reverse_lookup(&self, text: &str) -> Vec<Vec<u32>> {
+        self.reverse.get(text).cloned().unwrap_or_default()
+    }
+
+    fn is_writable(&self) -> bool {
+        self.path
+            .as_ref()
+            .and_then(|x| fs::metadata(x).ok())
+            .is_some_and(|x| !x.permissions().readonly())
+    }
+
+    fn set(&mut self, outline: &[u32], translation: Option<&str>) -> io::Result<()> {
+        let Some(path) = self.path.clone().filter(|_| self.is_writable()) else {
+            return Err(read_only());
+        };
+        let mut node = &mut self.root;
+        for (i, stroke) in outline.iter().enumerate() {
+            node = node
+                .children
+                .entry(*stroke)
+                .or_insert_with(|| Node::new("", i + 1));
+        }
+        let new = translation.map(|x| Translation::new(x.to_string(), outline.len()));
+        let old = std::mem::replace(&mut node.translation, new);
+        prune(&mut self.root);
+        if let Some(old) = old {
+            self.unindex(&old.raw, outline);
+        }
+        if let Some(t) = translation {
+            self.index(t, outline);
+        }
+
+        // Written next to the dictionary first, so that it's never left half written
+        let temp = path.with_extension("tmp");
+        fs::write(&temp, convert::to_tree(self.entries()))?;
+        fs::rename(temp, path)
+    }
 }
 impl TreeDict {
-    pub fn new(path: &str) -> Self {
-        let file = File::open(path).unwrap();
-        let lines = io::BufReader::new(file).lines();
+    // Load a dictionary file, named after the file.
+    pub fn new(path: &str) -> io::Result<Self> {
+        let file = File::open(path)?;
+        let mut dict = Self::from_reader(io::BufReader::new(file));
+        if let Some(name) = Path::new(path).file_name() {
+            dict.name = name.to_string_lossy().to_string();
+        }
+        dict.path = Some(PathBuf::from(path));
+        Ok(dict)
+    }
+
+    // Read a dictionary in the tab-indented tree format.
+    pub fn from_reader(reader: impl BufRead) -> Self {
+        let lines = reader.lines();
         let mut root = Node::new("", 0);
         let mut max_depth = 0;
 
+        let mut reverse: HashMap<String, Vec<Vec<u32>>> = HashMap::new();
         let mut last_parents = vec![];
-        for l in lines {
-            if let Ok(line) = l {
-                let mut parts = line.trim_start().split("\t");
-                let id = steno_to_id(parts.next().unwrap());
-                let trans = parts.next().unwrap_or("");
-
-                let depth = line.chars().take_while(|x| x == &'\t').count();
-                last_parents.resize(depth, 0);
-                let mut parent = &mut root.children;
-                for p in &last_parents {
-                    parent = &mut parent.get_mut(p).unwrap().children;
-                }
+        for line in lines.map_while(Result::ok) {
+            let mut parts = line.trim_start().split("\t");
+            let id = steno_to_id(parts.next().unwrap());
+            let trans = parts.next().unwrap_or("");
 
-                last_parents.push(id);
-                parent.insert(id, Node::new(trans, last_parents.len()));
-                if last_parents.len() > max_depth {
-                    max_depth = last_parents.len();
-                }
+            let depth = line.chars().take_while(|x| x == &'\t').count();
+            last_parents.resize(depth, 0);
+            let mut parent = &mut root.children;
+            for p in &last_parents {
+                parent = &mut parent.get_mut(p).unwrap().children;
+            }
+
+            last_parents.push(id);
+            parent.insert(id, Node::new(trans, last_parents.len()));
+            if !trans.is_empty() {
+                reverse
+                    .entry(trans.to_string())
+                    .or_default()
+                    .push(last_parents.clone());
+            }
+            if last_parents.len() > max_depth {
+                max_depth = last_parents.len();
             }
         }
 
-        Self { root: root }
+        for outlines in reverse.values_mut() {
+            outlines.sort();
+            outlines.dedup();
+        }
+        Self {
+            root,
+            reverse,
+            name: String::new(),
+            path: None,
+        }
     }
+
+    fn index(&mut self, raw: &str, outline: &[u32]) {
+        let outlines = self.reverse.entry(raw.to_string()).or_default();
+        if let Err(i) = outlines.binary_search_by(|x| x.as_slice().cmp(outline)) {
+            outlines.insert(i, outline.to_vec());
+        }
+    }
+
+    fn unindex(&mut self, raw: &str, outline: &[u32]) {
+        if let Some(outlines) = self.reverse.get_mut(raw) {
+            outlines.retain(|x| x != outline);
+            if outlines.is_empty() {
+                self.reverse.remove(raw);
+            }
+        }
+    }
+}
+
+// Remove the nodes that don't lead to a translation.
+fn prune(node: &mut Node) {
+    node.children.retain(|_, child| {
+        prune(child);
+        child.translation.is_some() || !child.children.is_empty()
+    });
 }
 
 macro_rules! check_contains {
@@ -100,10 +300,11 @@ macro_rules! check_contains {
         }
     };
 }
+#[derive(Default)]
 pub struct NumbersDict {}
 impl Dictionary for NumbersDict {
     fn get(&self, strokes: &[u32]) -> Option<Translation> {
-        let key = strokes[0];
+        let key = *strokes.first()?;
         if key & steno_to_id("#") == 0 || key & !steno_to_id("#STPHAOfpltdz") > 0 {
             return None;
         }
@@ -132,9 +333,58 @@ impl Dictionary for NumbersDict {
         let t = Translation::new(number, 1);
         Some(t)
     }
+
+    fn name(&self) -> &str {
+        "numbers"
+    }
 }
 impl NumbersDict {
     pub fn new() -> NumbersDict {
         NumbersDict {}
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn edit() {
+        let path = std::env::temp_dir().join(format!("moreover-edit-{}.txt", std::process::id()));
+        fs::write(&path, "KAt\tcat\n\ts\tcats\nTt\tthe\n").unwrap();
+        let mut dict = TreeDict::new(path.to_str().unwrap()).unwrap();
+        assert!(dict.is_writable());
+
+        let cats = [steno_to_id("KAt"), steno_to_id("s")];
+        dict.set(&cats, None).unwrap();
+        dict.set(&[steno_to_id("TKOg")], Some("dog")).unwrap();
+        assert!(dict.get(&cats[..1]).unwrap().complete);
+
+        // Reverse lookups follow the changes
+        assert!(dict.reverse_lookup("cats").is_empty());
+        assert_eq!(dict.reverse_lookup("dog"), [vec![steno_to_id("TKOg")]]);
+        dict.set(&[steno_to_id("KAT")], Some("cat")).unwrap();
+        dict.set(&[steno_to_id("Tt")], Some("cat")).unwrap();
+        assert!(dict.reverse_lookup("the").is_empty());
+        assert_eq!(dict.reverse_lookup("cat").len(), 3);
+        dict.set(&[steno_to_id("KAT")], None).unwrap();
+        dict.set(&[steno_to_id("Tt")], Some("the")).unwrap();
+        assert_eq!(dict.reverse_lookup("cat"), [cats[..1].to_vec()]);
+
+        let saved = TreeDict::new(path.to_str().unwrap()).unwrap();
+        fs::remove_file(&path).unwrap();
+        let mut entries = saved.entries();
+        entries.sort();
+        let mut expected = vec![
+            (vec![steno_to_id("KAt")], "cat".to_string()),
+            (vec![steno_to_id("Tt")], "the".to_string()),
+            (vec![steno_to_id("TKOg")], "dog".to_string()),
+        ];
+        expected.sort();
+        assert_eq!(entries, expected);
+
+        assert!(TreeDict::from_reader("".as_bytes())
+            .set(&cats, None)
+            .is_err());
+    }
+}
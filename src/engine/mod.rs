@@ -3,12 +3,15 @@
     Holds structs and various utility functions and constants.
 */
 
-mod dictionary;
-mod output;
+pub mod convert;
+pub mod dictionary;
+pub mod output;
 
 use dictionary::*;
 use enigo::Key;
+pub use output::orthography::Orthography;
 use output::translations_to_actions;
+use std::sync::Arc;
 
 const BUFFER_SIZE: usize = 500;
 pub const STENO_ORDER: &str = "^+#STKPWHRAO*eufrpblgtsdz";
@@ -41,12 +44,14 @@ const PSEUDOSTENO: [(&str, &str); 26] = [
     ("bg", "k"),
 ];
 
-#[derive(PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Action {
     Text(String),
     KeyDown(Key),
     KeyUp(Key),
     KeyClick(Key),
+    // Command for whatever runs the engine, from {PLOVER:...}
+    Command(String),
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -55,19 +60,63 @@ pub struct Translation {
     consumed: usize,
     complete: bool,
     non_undoable: bool,
+    source: Option<String>,
 }
 impl Translation {
-    fn new(s: String, d: usize) -> Self {
+    pub fn new(s: String, d: usize) -> Self {
         Translation {
             raw: s,
             consumed: d,
             complete: false,
             non_undoable: false,
+            source: None,
         }
     }
+
+    // The dictionary entry, before any formatting.
+    pub fn raw(&self) -> &str {
+        &self.raw
+    }
+    // Number of strokes the translation uses.
+    pub fn consumed(&self) -> usize {
+        self.consumed
+    }
+    // Whether no longer outline could extend this translation.
+    pub fn is_complete(&self) -> bool {
+        self.complete
+    }
+    pub fn set_complete(&mut self, complete: bool) {
+        self.complete = complete;
+    }
+    // Whether the translation only changes formatting, so undoing it also
+    // undoes the stroke before it.
+    pub fn is_non_undoable(&self) -> bool {
+        self.non_undoable
+    }
+    // Name of the dictionary the translation came from, or None if the
+    // strokes weren't found in any of them.
+    pub fn source(&self) -> Option<&str> {
+        self.source.as_deref()
+    }
+}
+
+// What the last stroke did, for showing on a paper tape.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Outcome {
+    // The translations ending with the stroke, like a word and its folded suffix
+    Translated(Vec<Translation>),
+    Untranslated,
+    Undo,
+    // Output was off
+    Ignored,
 }
 
 pub fn steno_to_id(s: &str) -> u32 {
+    parse_steno(s).unwrap()
+}
+
+// Like steno_to_id, but returns None if there are keys that aren't in the steno order.
+pub fn parse_steno(s: &str) -> Option<u32> {
     let mut pseudo = s.to_string();
     for (s, p) in PSEUDOSTENO {
         pseudo = pseudo.replace(p, s);
@@ -75,10 +124,23 @@ pub fn steno_to_id(s: &str) -> u32 {
 
     let mut result = 0u32;
     for c in pseudo.chars() {
-        let index = STENO_ORDER.find(c).unwrap();
+        let index = STENO_ORDER.find(c)?;
         result |= 1 << index;
     }
-    result
+    Some(result)
+}
+
+// Like id_to_steno, but without spaces for the keys that aren't pressed.
+pub fn id_to_compact_steno(u: u32) -> String {
+    id_to_steno(u).replace(' ', "")
+}
+
+// Parse an outline of strokes separated by "/" or whitespace.
+pub fn parse_outline(s: &str) -> Option<Vec<u32>> {
+    s.split(|c: char| c == '/' || c.is_whitespace())
+        .filter(|x| !x.is_empty())
+        .map(parse_steno)
+        .collect()
 }
 
 pub fn id_to_steno(u: u32) -> String {
@@ -95,9 +157,22 @@ pub fn id_to_steno(u: u32) -> String {
     s
 }
 
-fn diff<T: std::cmp::PartialEq>(a: &Vec<T>, b: &Vec<T>) -> usize {
+// Whether output is on after the output commands in the actions.
+fn output_change(actions: &[Action], enabled: bool) -> bool {
+    actions.iter().fold(enabled, |enabled, a| match a {
+        Action::Command(c) => match c.to_lowercase().as_str() {
+            "toggle" => !enabled,
+            "on" | "resume" => true,
+            "off" | "suspend" => false,
+            _ => enabled,
+        },
+        _ => enabled,
+    })
+}
+
+fn diff<T: std::cmp::PartialEq>(a: &[T], b: &[T]) -> usize {
     let mut i = 0;
-    if a.len() > 0 && b.len() > 0 {
+    if !a.is_empty() && !b.is_empty() {
         while a[i] == b[i] {
             i += 1;
             if i >= a.len() || i >= b.len() {
@@ -109,15 +184,37 @@ fn diff<T: std::cmp::PartialEq>(a: &Vec<T>, b: &Vec<T>) -> usize {
 }
 
 pub struct Engine {
-    dictionaries: Vec<Box<dyn Dictionary>>,
+    // The ones in use, highest priority first
+    dictionaries: Vec<LoadedDictionary>,
+    // Loaded, but not used by the current settings
+    unused: Vec<LoadedDictionary>,
+    // Ids in the order dictionaries were put in while running, highest
+    // priority first, which outlasts changes to the settings
+    order: Vec<usize>,
+    next_id: usize,
     strokes: Vec<u32>,
     translations: Vec<Translation>,
     suffix_folding: Vec<u32>,
+    orthography: Arc<Orthography>,
+    enabled: bool,
+    // Whether the last stroke turned output back on. The stroke that turned
+    // it off is formatting-only, so undoing right after would take the word
+    // before it too.
+    resumed: bool,
+    outcome: Outcome,
+}
+impl Default for Engine {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 impl Engine {
     pub fn new() -> Self {
         Engine {
-            dictionaries: vec![Box::new(NumbersDict::new())],
+            dictionaries: vec![LoadedDictionary::new(0, "", Box::new(NumbersDict::new()))],
+            unused: vec![],
+            order: vec![],
+            next_id: 1,
             strokes: vec![],
             translations: vec![],
             suffix_folding: vec![
@@ -126,20 +223,171 @@ impl Engine {
                 steno_to_id("s"),
                 steno_to_id("g"),
             ],
+            orthography: Orthography::english(),
+            enabled: true,
+            resumed: false,
+            outcome: Outcome::Ignored,
+        }
+    }
+    // Load a dictionary file, with a higher priority than the ones before it.
+    pub fn add_dict(&mut self, path: &str) -> std::io::Result<()> {
+        if path.split('.').next_back().unwrap() == "txt" {
+            self.add_dictionary(Box::new(TreeDict::new(path)?));
+        }
+        Ok(())
+    }
+    // Add a dictionary with the highest priority, returning its id.
+    pub fn add_dictionary(&mut self, dict: Box<dyn Dictionary + Send>) -> usize {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.dictionaries
+            .insert(0, LoadedDictionary::new(id, "", dict));
+        id
+    }
+    // Keep a dictionary loaded from a path, for settings to use with
+    // set_dictionaries, returning its id.
+    pub fn load_dictionary(&mut self, path: &str, dict: Box<dyn Dictionary + Send>) -> usize {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.unused.push(LoadedDictionary::new(id, path, dict));
+        id
+    }
+    // Whether a dictionary has been loaded from the path.
+    pub fn has_dictionary(&self, path: &str) -> bool {
+        self.dictionaries
+            .iter()
+            .chain(&self.unused)
+            .any(|x| !x.path().is_empty() && x.path() == path)
+    }
+    // Use the loaded dictionaries at the paths, highest priority last, and
+    // none of the others. The number dictionary stays below them. They're used
+    // as they were left, with their changes and whether they're turned on, and
+    // the ones that were moved while running keep the order they were put in.
+    pub fn set_dictionaries(&mut self, paths: &[impl AsRef<str>]) {
+        let mut loaded = std::mem::take(&mut self.unused);
+        loaded.append(&mut self.dictionaries);
+        for path in paths.iter().rev().map(|x| x.as_ref()) {
+            if let Some(i) = loaded.iter().position(|x| x.path() == path) {
+                self.dictionaries.push(loaded.remove(i));
+            }
+        }
+        if let Some(i) = loaded.iter().position(|x| x.id() == 0) {
+            self.dictionaries.push(loaded.remove(i));
+        }
+        self.unused = loaded;
+
+        // The moved ones are put in their order, in the places the settings
+        // have them, and the others stay where the settings put them
+        let rank = |x: &LoadedDictionary| self.order.iter().position(|id| *id == x.id());
+        let places: Vec<usize> = (0..self.dictionaries.len())
+            .filter(|i| rank(&self.dictionaries[*i]).is_some())
+            .collect();
+        let mut moved: Vec<LoadedDictionary> = places
+            .iter()
+            .rev()
+            .map(|i| self.dictionaries.remove(*i))
+            .collect();
+        moved.sort_by_key(|x| rank(x));
+        for (i, dict) in places.into_iter().zip(moved) {
+            self.dictionaries.insert(i, dict);
         }
     }
-    pub fn add_dict(&mut self, path: &str) {
-        match path.split('.').last().unwrap() {
-            "txt" => {
-                self.dictionaries.insert(0, Box::new(TreeDict::new(path)));
+
+    // The dictionaries, highest priority first, including the ones that are
+    // turned off.
+    pub fn dictionaries(&self) -> impl Iterator<Item = &LoadedDictionary> {
+        self.dictionaries.iter()
+    }
+    pub fn dictionary_mut(&mut self, i: usize) -> Option<&mut (dyn Dictionary + Send)> {
+        Some(self.dictionaries.get_mut(i)?.dictionary_mut())
+    }
+
+    // Turn a dictionary on or off by its id, returning whether there is one.
+    pub fn set_dictionary_enabled(&mut self, id: usize, enabled: bool) -> bool {
+        match self.dictionaries.iter_mut().find(|x| x.id() == id) {
+            Some(dict) => {
+                dict.set_enabled(enabled);
+                true
             }
-            _ => {}
+            None => false,
+        }
+    }
+    // Move a dictionary by its id to a priority, where 0 is the highest,
+    // returning whether there is one. The order is kept when the settings
+    // change.
+    pub fn set_dictionary_priority(&mut self, id: usize, priority: usize) -> bool {
+        let Some(i) = self.dictionaries.iter().position(|x| x.id() == id) else {
+            return false;
+        };
+        let dict = self.dictionaries.remove(i);
+        let priority = priority.min(self.dictionaries.len());
+        self.dictionaries.insert(priority, dict);
+
+        // Dictionaries that aren't in use keep their places among the others
+        let old = std::mem::take(&mut self.order);
+        self.order = self.dictionaries.iter().map(|x| x.id()).collect();
+        for (i, id) in old.into_iter().enumerate() {
+            if !self.order.contains(&id) {
+                self.order.insert(i.min(self.order.len()), id);
+            }
+        }
+        true
+    }
+
+    // The translation of exactly the outline in each dictionary, by priority,
+    // including the ones that are turned off. The first one found in a
+    // dictionary that's on is the one that's used.
+    pub fn lookup_all(&self, outline: &[u32]) -> Vec<Option<Translation>> {
+        if outline.is_empty() {
+            return vec![None; self.dictionaries.len()];
         }
+        self.dictionaries
+            .iter()
+            .map(|x| x.get(outline).filter(|t| t.consumed == outline.len()))
+            .collect()
+    }
+
+    pub fn set_orthography(&mut self, orthography: impl Into<Arc<Orthography>>) {
+        self.orthography = orthography.into();
+    }
+
+    // Whether strokes are translated, or only checked for turning output on.
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
     }
 
     // Take in a stroke, translate it, compare it with the previous state, and
     // return necessary deletions and additions.
     pub fn process_stroke(&mut self, stroke: u32) -> (Vec<Action>, Vec<Action>) {
+        if !self.enabled {
+            // Only single stroke commands are looked up, and only the ones
+            // that turn output on have any effect
+            self.outcome = Outcome::Ignored;
+            let Some(mut t) = self.lookup_helper(&[stroke]) else {
+                return (vec![], vec![]);
+            };
+            let commands: Vec<Action> =
+                translations_to_actions(std::slice::from_mut(&mut t), &self.orthography)
+                    .into_iter()
+                    .filter(|x| matches!(x, Action::Command(_)))
+                    .collect();
+            self.enabled = output_change(&commands, false);
+            if !self.enabled {
+                return (vec![], vec![]);
+            }
+            self.outcome = Outcome::Translated(vec![t]);
+            self.resumed = true;
+            return (vec![], commands);
+        }
+        // Undoing the stroke that turned output on does nothing
+        if std::mem::take(&mut self.resumed) && stroke == steno_to_id("*") {
+            self.outcome = Outcome::Undo;
+            return (vec![], vec![]);
+        }
+
         self.flush_buffer();
 
         let ti = self
@@ -152,7 +400,8 @@ impl Engine {
         let mut stroke_length: usize = old_translations.iter().map(|x| x.consumed).sum();
 
         if stroke == steno_to_id("*") {
-            if self.strokes.len() == 0 {
+            self.outcome = Outcome::Undo;
+            if self.strokes.is_empty() {
                 return (vec![], vec![]);
             }
             let num_non_undoable = self
@@ -173,21 +422,39 @@ impl Engine {
         let new_strokes = &self.strokes[self.strokes.len() - stroke_length..];
         let mut new_translations = self.translate_strokes(new_strokes);
 
-        let mut old_actions = translations_to_actions(&mut old_translations);
-        let mut new_actions = translations_to_actions(&mut new_translations);
+        let mut old_actions = translations_to_actions(&mut old_translations, &self.orthography);
+        let mut new_actions = translations_to_actions(&mut new_translations, &self.orthography);
 
         let di = diff(&old_translations, &new_translations);
         self.translations.drain(ti + di..);
-        for i in di..new_translations.len() {
-            self.translations.push(new_translations[i].clone());
-        }
+        self.translations.extend_from_slice(&new_translations[di..]);
 
         let ai = diff(&old_actions, &new_actions);
         old_actions.drain(0..ai);
         new_actions.drain(0..ai);
+        self.enabled = output_change(&new_actions, true);
+        if stroke != steno_to_id("*") {
+            self.outcome = self.last_outcome();
+        }
         (old_actions, new_actions)
     }
 
+    // What the last stroke did.
+    pub fn outcome(&self) -> &Outcome {
+        &self.outcome
+    }
+
+    // The translations from the last one that uses a stroke.
+    fn last_outcome(&self) -> Outcome {
+        let i = self.translations.iter().rposition(|x| x.consumed > 0);
+        match i {
+            Some(i) if self.translations[i].source.is_some() => {
+                Outcome::Translated(self.translations[i..].to_vec())
+            }
+            _ => Outcome::Untranslated,
+        }
+    }
+
     // Takes a slice of strokes, and greedily translates them.
     fn translate_strokes(&self, strokes: &[u32]) -> Vec<Translation> {
         let mut translations = vec![];
@@ -205,7 +472,7 @@ impl Engine {
             }
             // Failed translation
             else {
-                translations.push(Translation::new(strokes[i].to_string(), 1));
+                translations.push(Translation::new(id_to_compact_steno(strokes[i]), 1));
                 i += 1;
             }
         }
@@ -245,15 +512,33 @@ impl Engine {
 
     // Get a translation.
     fn lookup_helper(&self, strokes: &[u32]) -> Option<Translation> {
-        for dict in &self.dictionaries {
-            let trans = dict.get(strokes);
-            if trans.is_some() {
-                return trans;
+        for dict in self.dictionaries.iter().filter(|x| x.is_enabled()) {
+            if let Some(mut trans) = dict.get(strokes) {
+                trans.source = Some(dict.name().to_string());
+                return Some(trans);
             }
         }
         None
     }
 
+    // Find the outlines that translate to the given text, shortest first.
+    // Outlines overridden by a higher priority dictionary are left out.
+    pub fn reverse_lookup(&self, text: &str) -> Vec<Vec<u32>> {
+        let mut outlines = vec![];
+        for dict in self.dictionaries.iter().filter(|x| x.is_enabled()) {
+            for outline in dict.reverse_lookup(text) {
+                let found = self
+                    .lookup_helper(&outline)
+                    .is_some_and(|x| x.raw == text && x.consumed == outline.len());
+                if found && !outlines.contains(&outline) {
+                    outlines.push(outline);
+                }
+            }
+        }
+        outlines.sort_by_key(|x| x.len());
+        outlines
+    }
+
     // Limit the stroke buffer size.
     fn flush_buffer(&mut self) {
         let mut n = 0;
@@ -266,3 +551,122 @@ impl Engine {
         self.strokes.drain(0..self.strokes.len() - n);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn outcomes() {
+        let mut engine = Engine::new();
+        engine.add_dictionary(Box::new(TreeDict::from_reader(
+            "KAt\tcat\n\ts\tcats\nTO\t{PLOVER:OFF}\n".as_bytes(),
+        )));
+
+        engine.process_stroke(steno_to_id("KAt"));
+        let Outcome::Translated(t) = engine.outcome() else {
+            panic!("{:?}", engine.outcome());
+        };
+        assert_eq!((t[0].raw(), t[0].source()), ("cat", Some("")));
+        engine.process_stroke(steno_to_id("s"));
+        let Outcome::Translated(t) = engine.outcome() else {
+            panic!("{:?}", engine.outcome());
+        };
+        assert_eq!(t[0].raw(), "cats");
+
+        engine.process_stroke(steno_to_id("STK"));
+        assert_eq!(engine.outcome(), &Outcome::Untranslated);
+        engine.process_stroke(steno_to_id("*"));
+        assert_eq!(engine.outcome(), &Outcome::Undo);
+        engine.process_stroke(steno_to_id("#T"));
+        let Outcome::Translated(t) = engine.outcome() else {
+            panic!("{:?}", engine.outcome());
+        };
+        assert_eq!(t[0].source(), Some("numbers"));
+
+        engine.process_stroke(steno_to_id("TO"));
+        engine.process_stroke(steno_to_id("KAt"));
+        assert_eq!(engine.outcome(), &Outcome::Ignored);
+    }
+
+    #[test]
+    fn dictionaries() {
+        let dict = |s: &str| -> Box<dyn Dictionary + Send> {
+            Box::new(TreeDict::from_reader(s.as_bytes()))
+        };
+        let translate = |engine: &mut Engine, s| {
+            engine.process_stroke(steno_to_id(s));
+            match engine.outcome() {
+                Outcome::Translated(t) => t[0].raw().to_string(),
+                _ => String::new(),
+            }
+        };
+        let mut engine = Engine::new();
+        engine.load_dictionary("cat.txt", dict("KAt\tcat\n"));
+        engine.load_dictionary("cap.txt", dict("KAt\tcap\n"));
+        engine.set_dictionaries(&["cat.txt", "cap.txt"]);
+        let ids: Vec<usize> = engine.dictionaries().map(|x| x.id()).collect();
+        assert_eq!(translate(&mut engine, "KAt"), "cap");
+
+        assert!(engine.set_dictionary_enabled(ids[0], false));
+        assert_eq!(translate(&mut engine, "KAt"), "cat");
+        assert!(engine.lookup_all(&[steno_to_id("KAt")])[0].is_some());
+        assert!(engine.lookup_all(&[]).iter().all(|x| x.is_none()));
+        assert!(engine.set_dictionary_enabled(ids[0], true));
+        assert!(engine.set_dictionary_priority(ids[0], 1));
+        assert_eq!(translate(&mut engine, "KAt"), "cat");
+        // Past the lowest priority
+        assert!(engine.set_dictionary_priority(ids[1], 10));
+        assert_eq!(engine.dictionaries().last().unwrap().id(), ids[1]);
+        assert!(!engine.set_dictionary_enabled(100, false));
+
+        // Dictionaries stay off when the settings change
+        engine.set_dictionary_enabled(ids[2], false);
+        engine.set_dictionary_enabled(ids[0], false);
+        assert_eq!(translate(&mut engine, "#T"), "");
+        engine.set_dictionaries(&["cat.txt"]);
+        assert_eq!(engine.dictionaries().count(), 2);
+        // Which is still below the numbers, where it was moved to
+        let numbers = engine.dictionaries().next().unwrap();
+        assert_eq!((numbers.id(), numbers.is_enabled()), (ids[2], false));
+        assert!(engine.has_dictionary("cap.txt"));
+        engine.set_dictionaries(&["cat.txt", "cap.txt", "missing.txt"]);
+        let cap = engine.dictionaries().next().unwrap();
+        assert_eq!((cap.id(), cap.is_enabled()), (ids[0], false));
+        assert_eq!(engine.dictionaries().count(), 3);
+    }
+
+    #[test]
+    fn priorities() {
+        let mut engine = Engine::new();
+        for path in ["a.txt", "b.txt", "c.txt"] {
+            engine.load_dictionary(path, Box::new(TreeDict::from_reader("".as_bytes())));
+        }
+        let order = |engine: &Engine| -> Vec<String> {
+            engine
+                .dictionaries()
+                .map(|x| x.path().to_string())
+                .collect()
+        };
+        engine.set_dictionaries(&["a.txt", "b.txt", "c.txt"]);
+        assert_eq!(order(&engine), ["c.txt", "b.txt", "a.txt", ""]);
+
+        // Moved to the top, and kept there by another profile
+        let a = engine.dictionaries().nth(2).unwrap().id();
+        engine.set_dictionary_priority(a, 0);
+        assert_eq!(order(&engine), ["a.txt", "c.txt", "b.txt", ""]);
+        engine.set_dictionaries(&["a.txt", "c.txt"]);
+        assert_eq!(order(&engine), ["a.txt", "c.txt", ""]);
+
+        // Moving one while another isn't used keeps that one's place
+        let c = engine.dictionaries().nth(1).unwrap().id();
+        engine.set_dictionary_priority(c, 0);
+        engine.set_dictionaries(&["a.txt", "b.txt", "c.txt"]);
+        assert_eq!(order(&engine), ["c.txt", "a.txt", "b.txt", ""]);
+
+        // Ones that were never moved follow the settings
+        engine.load_dictionary("d.txt", Box::new(TreeDict::from_reader("".as_bytes())));
+        engine.set_dictionaries(&["d.txt", "a.txt", "b.txt", "c.txt"]);
+        assert_eq!(order(&engine), ["c.txt", "a.txt", "b.txt", "d.txt", ""]);
+    }
+}
@@ -1,17 +1,20 @@
-mod orthography;
+pub mod orthography;
 
 use super::{Action, Translation};
 use enigo::Key;
 use lazy_static::lazy_static;
-use regex::Regex;
+use orthography::Orthography;
+use regex::{Captures, Regex};
 
 lazy_static! {
-    static ref ESCAPE_NEWLINE: Regex = Regex::new(r"(^|[^\\])\\n").unwrap();
-    static ref ESCAPED_BRACKETS: Regex = Regex::new(r"\\(\{|\}|\\)").unwrap();
-    static ref BRACKETS: Regex = Regex::new(r"(?s)\{(.*?)\}").unwrap();
+    static ref ESCAPES: Regex = Regex::new(r"\\(n|t|\{|\}|\\)").unwrap();
+    // Escaped brackets don't start or end a command
+    static ref BRACKETS: fancy_regex::Regex =
+        fancy_regex::Regex::new(r"(?s)(?<!\\)\{(.*?)(?<!\\)\}").unwrap();
     static ref PUNCT_CAPS: Regex = Regex::new(r"^(\.|!|\?)$").unwrap();
     static ref PUNCT_SPACE: Regex = Regex::new(r"^(,|:|;)$").unwrap();
     static ref IS_COMMAND: Regex = Regex::new(r"^#.+?").unwrap();
+    static ref IS_ENGINE_COMMAND: Regex = Regex::new(r"(?i)^plover:(.+)$").unwrap();
     static ref IS_GLUE: Regex = Regex::new(r"^&.+?").unwrap();
 }
 
@@ -47,17 +50,20 @@ mod format {
     pub const CAPITALIZE: i32 = 1 << 3;
     pub const LOWERCASE: i32 = 1 << 4;
     pub const UPPERCASE: i32 = 1 << 5;
+    pub const ENGINE_COMMAND: i32 = 1 << 6;
 
     pub const RESET_CAPS: i32 = !(CAPITALIZE | LOWERCASE | UPPERCASE);
 }
 
-pub fn translations_to_actions(translations: &mut Vec<Translation>) -> Vec<Action> {
+pub fn translations_to_actions(
+    translations: &mut [Translation],
+    orthography: &Orthography,
+) -> Vec<Action> {
     let mut strings = vec![];
     let mut formats = vec![0];
 
-    for t in 0..translations.len() {
-        let non_undoable = process_raw(&translations[t].raw, &mut strings, &mut formats);
-        translations[t].non_undoable = non_undoable;
+    for t in translations.iter_mut() {
+        t.non_undoable = process_raw(&t.raw, &mut strings, &mut formats, orthography);
     }
 
     let mut actions = vec![];
@@ -67,31 +73,50 @@ pub fn translations_to_actions(translations: &mut Vec<Translation>) -> Vec<Actio
     actions
 }
 
-pub fn process_raw(s: &str, strings: &mut Vec<String>, formats: &mut Vec<i32>) -> bool {
+pub fn process_raw(
+    s: &str,
+    strings: &mut Vec<String>,
+    formats: &mut Vec<i32>,
+    orthography: &Orthography,
+) -> bool {
     let commands: Vec<&str> = BRACKETS
         .captures_iter(s)
+        .filter_map(|x| x.ok())
         .map(|x| x.get(1).unwrap().as_str())
         .collect();
-    let texts = BRACKETS.split(s);
+    let texts = BRACKETS.split(s).filter_map(|x| x.ok());
 
     let mut non_undoable = true;
     for (i, s) in texts.enumerate() {
-        if s.len() > 0 {
-            let newlined = ESCAPE_NEWLINE.replace_all(s, "${1}\n").to_string();
-            let text = ESCAPED_BRACKETS.replace_all(&newlined, "${1}");
-            strings.push(text.to_string());
+        if !s.is_empty() {
+            strings.push(unescape(s));
             formats.push(0);
             non_undoable = false;
         }
         if i < commands.len() {
-            non_undoable &= process_command(commands[i], strings, formats);
+            non_undoable &= process_command(commands[i], strings, formats, orthography);
         }
     }
     non_undoable
 }
 
+// Turn escape sequences into the characters they stand for.
+// Control characters are kept as text, and only become keypresses when
+// they're typed out, so that they can be undone like any other character.
+fn unescape(s: &str) -> String {
+    ESCAPES
+        .replace_all(s, |caps: &Captures| match &caps[1] {
+            "n" => "\n".to_string(),
+            "t" => "\t".to_string(),
+            c => c.to_string(),
+        })
+        .to_string()
+}
+
 fn to_action(mut s: String, f: i32) -> Vec<Action> {
-    if f & format::COMMAND > 0 {
+    if f & format::ENGINE_COMMAND > 0 {
+        vec![Action::Command(s)]
+    } else if f & format::COMMAND > 0 {
         let mut keys = vec![];
         if s.ends_with(",") {
             s.pop();
@@ -123,7 +148,12 @@ fn to_action(mut s: String, f: i32) -> Vec<Action> {
     }
 }
 
-fn process_command(s: &str, strings: &mut Vec<String>, formats: &mut Vec<i32>) -> bool {
+fn process_command(
+    s: &str,
+    strings: &mut Vec<String>,
+    formats: &mut Vec<i32>,
+    orthography: &Orthography,
+) -> bool {
     let mut f = formats.pop().unwrap();
     let mut next = 0;
     let mut non_undoable = true;
@@ -145,7 +175,7 @@ fn process_command(s: &str, strings: &mut Vec<String>, formats: &mut Vec<i32>) -
             formats.push(f & format::RESET_CAPS | format::CAPITALIZE);
         }
         "*-|" => {
-            if formats.len() > 0 {
+            if !formats.is_empty() {
                 let mut i = formats.len();
                 while i > 0 {
                     i -= 1;
@@ -153,7 +183,7 @@ fn process_command(s: &str, strings: &mut Vec<String>, formats: &mut Vec<i32>) -
                         break;
                     }
                 }
-                formats[i] = formats[i] | format::CAPITALIZE;
+                formats[i] |= format::CAPITALIZE;
             }
             formats.push(f);
         }
@@ -162,7 +192,7 @@ fn process_command(s: &str, strings: &mut Vec<String>, formats: &mut Vec<i32>) -
             formats.push(f & format::RESET_CAPS | format::LOWERCASE);
         }
         "*>" => {
-            if formats.len() > 0 {
+            if !formats.is_empty() {
                 let mut i = formats.len();
                 while i > 0 {
                     i -= 1;
@@ -170,7 +200,7 @@ fn process_command(s: &str, strings: &mut Vec<String>, formats: &mut Vec<i32>) -
                         break;
                     }
                 }
-                formats[i] = formats[i] | format::LOWERCASE;
+                formats[i] |= format::LOWERCASE;
             }
             formats.push(f);
         }
@@ -179,7 +209,7 @@ fn process_command(s: &str, strings: &mut Vec<String>, formats: &mut Vec<i32>) -
             formats.push(f & format::RESET_CAPS | format::UPPERCASE);
         }
         "*<" => {
-            if formats.len() > 0 {
+            if !formats.is_empty() {
                 let mut i = formats.len();
                 while i > 0 {
                     i -= 1;
@@ -187,7 +217,7 @@ fn process_command(s: &str, strings: &mut Vec<String>, formats: &mut Vec<i32>) -
                         break;
                     }
                 }
-                formats[i] = formats[i] | format::UPPERCASE;
+                formats[i] |= format::UPPERCASE;
             }
             formats.push(f);
         }
@@ -207,6 +237,11 @@ fn process_command(s: &str, strings: &mut Vec<String>, formats: &mut Vec<i32>) -
                 formats.push(format::ATTACH);
                 formats.push(0);
                 non_undoable = false;
+            } else if let Some(caps) = IS_ENGINE_COMMAND.captures(s) {
+                // Engine commands don't affect the text around them
+                strings.push(caps[1].to_string());
+                formats.push(format::ENGINE_COMMAND | format::ATTACH);
+                formats.push(f);
             } else if IS_COMMAND.is_match(s) {
                 let mut command = s.to_string();
                 command.remove(0);
@@ -218,7 +253,7 @@ fn process_command(s: &str, strings: &mut Vec<String>, formats: &mut Vec<i32>) -
                 glued.remove(0);
                 strings.push(glued);
                 formats.push(
-                    if formats.len() > 0 && formats[formats.len() - 1] & format::GLUE > 0 {
+                    if !formats.is_empty() && formats[formats.len() - 1] & format::GLUE > 0 {
                         f | format::ATTACH | format::GLUE
                     } else {
                         f | format::GLUE
@@ -227,7 +262,7 @@ fn process_command(s: &str, strings: &mut Vec<String>, formats: &mut Vec<i32>) -
                 formats.push(0);
                 non_undoable = false;
             } else {
-                let mut text = s.to_string();
+                let mut text = unescape(s);
                 let mut needs_orthography = false;
                 if text.starts_with("^") {
                     text.remove(0);
@@ -238,10 +273,10 @@ fn process_command(s: &str, strings: &mut Vec<String>, formats: &mut Vec<i32>) -
                     text.pop();
                     next |= format::ATTACH;
                 }
-                if needs_orthography && strings.len() > 0 {
+                if needs_orthography && !strings.is_empty() {
                     if formats[formats.len() - 1] & format::COMMAND == 0 {
                         let last = strings.pop().unwrap();
-                        let new = orthography::apply_orthography(&last, &text);
+                        let new = orthography.apply(&last, &text);
                         strings.push(new);
                         formats.push(next);
                     } else {
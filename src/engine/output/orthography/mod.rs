@@ -1,6 +1,24 @@
+/*
+    Orthography rules for attaching suffixes to words.
+    Rules, the word list and the exceptions are plain text, so they can be swapped
+    out for other spellings or languages. See english_rules.txt for the rule format.
+*/
+
+use fancy_regex::Regex;
 use lazy_static::lazy_static;
-use regex::Regex;
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::fs;
+use std::sync::Arc;
+
+const ENGLISH_RULES: &str = include_str!("english_rules.txt");
+const ENGLISH_WORDS: &str = include_str!("english.txt");
+const ENGLISH_EXCEPTIONS: &str = include_str!("english_exceptions.txt");
+
+lazy_static! {
+    static ref ENGLISH: Arc<Orthography> =
+        Arc::new(Orthography::new(ENGLISH_RULES, ENGLISH_WORDS, ENGLISH_EXCEPTIONS).unwrap());
+}
 
 #[derive(Debug)]
 enum ReplaceItem {
@@ -10,182 +28,373 @@ enum ReplaceItem {
 }
 
 #[derive(Debug)]
-struct Orthography {
+struct Rule {
     word: Regex,
     suffix: Regex,
     replace: Vec<ReplaceItem>,
 }
 
-pub fn apply_orthography(word: &str, suffix: &str) -> String {
-    let join = word.to_string() + suffix;
-    if ENGLISH_DICTIONARY.contains(&join.to_lowercase()) {
-        return join;
-    }
-    for ortho in ORTHOGRAPHIES.iter() {
-        if let (Some(word_caps), Some(suffix_caps)) =
-            (ortho.word.captures(word), ortho.suffix.captures(suffix))
-        {
-            let mut new = String::new();
-            for replace in &ortho.replace {
-                new.push_str(match replace {
-                    ReplaceItem::WordCapture(i) => word_caps.get(*i).unwrap().as_str(),
-                    ReplaceItem::SuffixCapture(i) => suffix_caps.get(*i).unwrap().as_str(),
-                    ReplaceItem::String(s) => &s,
-                })
+#[derive(Debug)]
+pub struct Orthography {
+    rules: Vec<Rule>,
+    words: HashMap<String, u64>,
+    // Words and the suffixes they're joined to unchanged
+    exceptions: HashSet<(String, String)>,
+}
+impl Orthography {
+    // Build an orthography from the contents of a rules file, a word list,
+    // and a list of words and suffixes the rules shouldn't change.
+    pub fn new(rules: &str, words: &str, exceptions: &str) -> Result<Self, Box<dyn Error>> {
+        let mut parsed = vec![];
+        for (i, line) in rules.lines().enumerate() {
+            if line.trim().is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let rule = parse_rule(line).map_err(|e| format!("line {}: {}", i + 1, e))?;
+            parsed.push(rule);
+        }
+
+        Ok(Self {
+            rules: parsed,
+            words: parse_frequencies(words),
+            exceptions: parse_exceptions(exceptions)?,
+        })
+    }
+
+    // Load the rules, word list and exceptions from files.
+    // Any of them can be left out to use the English default.
+    pub fn load(
+        rules: Option<&str>,
+        words: Option<&str>,
+        exceptions: Option<&str>,
+    ) -> Result<Self, Box<dyn Error>> {
+        Self::new(
+            &read_or(rules, ENGLISH_RULES)?,
+            &read_or(words, ENGLISH_WORDS)?,
+            &read_or(exceptions, ENGLISH_EXCEPTIONS)?,
+        )
+    }
+
+    pub fn english() -> Arc<Self> {
+        ENGLISH.clone()
+    }
+
+    // Attach a suffix to a word. Every matching rule gives a candidate, and the
+    // most frequent one in the word list wins, with earlier rules winning ties.
+    // If no candidate is a known word, the plain join is used if it is one,
+    // and otherwise the first rule's output.
+    pub fn apply(&self, word: &str, suffix: &str) -> String {
+        let join = word.to_string() + suffix;
+        let key = (word.to_lowercase(), suffix.to_lowercase());
+        if self.exceptions.contains(&key) {
+            return join;
+        }
+
+        let candidates: Vec<String> = self
+            .rules
+            .iter()
+            .filter_map(|x| x.apply(word, suffix))
+            .collect();
+
+        let mut best: Option<(&String, u64)> = None;
+        for c in &candidates {
+            if let Some(&f) = self.words.get(&c.to_lowercase()) {
+                if best.is_none_or(|(_, b)| f > b) {
+                    best = Some((c, f));
+                }
             }
-            return new;
+        }
+        if let Some((c, _)) = best {
+            c.clone()
+        } else if self.words.contains_key(&join.to_lowercase()) {
+            join
+        } else {
+            candidates.into_iter().next().unwrap_or(join)
         }
     }
-    join
 }
 
-macro_rules! ortho_append {
-    ($word:expr, $suffix: expr, $replace: expr) => {
-        Orthography {
-            word: Regex::new($word).unwrap(),
-            suffix: Regex::new($suffix).unwrap(),
-            replace: vec![
-                ReplaceItem::WordCapture(1),
-                ReplaceItem::String($replace.to_string()),
-            ],
+impl Rule {
+    fn apply(&self, word: &str, suffix: &str) -> Option<String> {
+        // Errors only happen when a lookaround backtracks too much, so
+        // they're treated as not matching.
+        let word_caps = self.word.captures(word).ok()??;
+        let suffix_caps = self.suffix.captures(suffix).ok()??;
+
+        let mut new = String::new();
+        for replace in &self.replace {
+            new.push_str(match replace {
+                ReplaceItem::WordCapture(i) => word_caps.get(*i).map_or("", |x| x.as_str()),
+                ReplaceItem::SuffixCapture(i) => suffix_caps.get(*i).map_or("", |x| x.as_str()),
+                ReplaceItem::String(s) => s,
+            })
         }
-    };
+        Some(new)
+    }
 }
-macro_rules! ortho_insert {
-    ($word:expr, $suffix: expr, $replace: expr) => {
-        Orthography {
-            word: Regex::new($word).unwrap(),
-            suffix: Regex::new($suffix).unwrap(),
-            replace: vec![
-                ReplaceItem::WordCapture(1),
-                ReplaceItem::String($replace.to_string()),
-                ReplaceItem::SuffixCapture(1),
-            ],
+
+fn read_or(path: Option<&str>, default: &str) -> Result<String, Box<dyn Error>> {
+    match path {
+        Some(p) => Ok(fs::read_to_string(p).map_err(|e| format!("{}: {}", p, e))?),
+        None => Ok(default.to_string()),
+    }
+}
+
+// Word lists have one word per line, optionally followed by a tab and how
+// often the word is used. Words without a count are the least frequent.
+fn parse_frequencies(s: &str) -> HashMap<String, u64> {
+    let mut words = HashMap::new();
+    for line in s.lines() {
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
         }
-    };
+        let mut parts = line.split('\t');
+        let word = parts.next().unwrap().trim().to_lowercase();
+        let count = parts
+            .next()
+            .and_then(|x| x.trim().parse().ok())
+            .unwrap_or(0);
+        words.insert(word, count);
+    }
+    words
 }
-macro_rules! ortho {
-    ($word:expr, $suffix: expr, $replace: expr) => {
-        Orthography {
-            word: Regex::new($word).unwrap(),
-            suffix: Regex::new($suffix).unwrap(),
-            replace: $replace,
+
+// Exception lists have a word, a tab, and space separated suffixes per line,
+// and may have comments.
+fn parse_exceptions(s: &str) -> Result<HashSet<(String, String)>, Box<dyn Error>> {
+    let mut exceptions = HashSet::new();
+    for (i, line) in s.lines().enumerate() {
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
         }
-    };
-    ($word:expr, $suffix: expr) => {
-        Orthography {
-            word: Regex::new($word).unwrap(),
-            suffix: Regex::new($suffix).unwrap(),
-            replace: vec![ReplaceItem::WordCapture(1), ReplaceItem::SuffixCapture(1)],
+        let (word, suffixes) = line
+            .split_once('\t')
+            .filter(|(_, x)| !x.trim().is_empty())
+            .ok_or_else(|| format!("line {}: expected a word and suffixes", i + 1))?;
+        let word = word.trim().to_lowercase();
+        for suffix in suffixes.split_whitespace() {
+            exceptions.insert((word.clone(), suffix.to_lowercase()));
         }
-    };
+    }
+    Ok(exceptions)
 }
 
-lazy_static! {
-    static ref ENGLISH_DICTIONARY: HashSet<String> = HashSet::from_iter(
-        include_str!("english.txt")
-            .lines()
-            .map(|x| x.to_string().to_lowercase())
-    );
-    static ref ORTHOGRAPHIES: Vec<Orthography> = vec![
-
-        // artistic + ly = artistically
-        ortho_append!(r"^(.*[aeiou]c)$", r"^ly$", r"ally"),
-        // questionable +ly = questionably
-        ortho_append!(r"^(.+[aeioubmnp])le$", r"^ly$", r"ly"),
-
-        // statute + ry = statutory
-        ortho_append!(r"^(.*t)e$", r"^(ry|ary)$", r"ory"),
-        // confirm +tory = confirmatory (*confirmtory)
-        ortho_insert!(r"^(.+)m$", r"^tor(y|ily)$", r"mator"),
-        // supervise +ary = supervisory (*supervisary)
-        ortho_insert!(r"^(.+)se$", r"^ar(y|ies)$", r"or"),
-
-        // frequent + cy = frequency (tcy/tecy removal)
-        ortho_append!(r"^(.*[naeiou])te?$", r"^cy$", r"cy"),
-
-        // establish + s = establishes (sibilant pluralization)
-        ortho_append!(r"^(.*(?:s|sh|x|z|zh))$", r"^s$", r"es"),
-        // speech + s = speeches (soft ch pluralization).
-        // NOTE: Lookarounds aren't supported, so they're just not here
-        ortho_append!(r"^(.*(?:oa|ea|i|ee|oo|au|ou|l|n|[gin]ar|t)ch)$", r"^s$", r"es"),
-        // cherry + s = cherries (consonant + y pluralization)
-        ortho_append!(r"^(.+[bcdfghjklmnpqrstvwxz])y$", r"^s$", r"ies"),
-
-        // die+ing = dying
-        ortho_append!(r"^(.+)ie$", r"^ing$", r"ying"),
-        // metallurgy + ist = metallurgist
-        ortho_append!(r"^(.+[cdfghlmnpr])y$", r"^ist$", r"ist"),
-        // beauty + ful = beautiful (y -> i)
-        ortho_insert!(r"^(.+[bcdfghjklmnpqrstvwxz])y$", r"^([a-hj-xz].*)$", r"i"),
-
-        // write + en = written
-        ortho_append!(r"^(.+)te$", r"^en$", r"tten"),
-        // Minessota +en = Minessotan (*Minessotaen)
-        ortho!(r"^(.+[ae])$", r"^e(n|ns)$"),
-
-        // ceremony +ial = ceremonial (*ceremonyial)
-        ortho!(r"^(.+)y$", r"^(ial|ially)$"),
-
-        // spaghetti +ification = spaghettification (*spaghettiification)
-        ortho_insert!(r"^(.+)i$", r"^if(y|ying|ied|ies|ication|ications)$", r"if"),
-
-        // fantastic +ical = fantastical (*fantasticcal)
-        ortho!(r"^(.+)ic$", r"^(ical|ically)$"),
-        // NOTE: The above regex doesn't match fantastic + al.
-        // For some reason, this isn't in the Plover orthography.
-        ortho!(r"^(.+ic)$", r"^(al)$"),
-        // epistomology +ical = epistomological
-        ortho_insert!(r"^(.+)ology$", r"^ic(al|ally)$", r"ologic"),
-        // oratory +ical = oratorical (*oratoryical)
-        ortho_insert!(r"^(.*)ry$", r"^ica(l|lly|lity)$", r"rica"),
-
-        // radical +ist = radicalist (*radicallist)
-        ortho_insert!(r"^(.*[l])$", r"^is(t|ts)$", r"is"),
-
-        // complementary +ity = complementarity (*complementaryity)
-        ortho_append!(r"^(.*)ry$", r"^ity$", r"rity"),
-        // disproportional +ity = disproportionality (*disproportionallity)
-        ortho_append!(r"^(.*)l$", r"^ity$", r"lity"),
-
-        // perform +tive = performative (*performtive)
-        ortho_insert!(r"^(.+)rm$", r"^tiv(e|ity|ities)$", r"rmativ"),
-        // restore +tive = restorative
-        ortho_insert!(r"^(.+)e$", r"^tiv(e|ity|ities)$", r"ativ"),
-
-        // token +ize = tokenize (*tokennize)
-        // token +ise = tokenise (*tokennise)
-        ortho_insert!(r"^(.+)y$", r"^iz(e|es|ing|ed|er|ers|ation|ations|able|ability)$", r"iz"),
-        ortho_insert!(r"^(.+)y$", r"^is(e|es|ing|ed|er|ers|ation|ations|able|ability)$", r"is"),
-        // conditional +ize = conditionalize (*conditionallize)
-        ortho_insert!(r"^(.+)al$", r"^iz(e|ed|es|ing|er|ers|ation|ations|m|ms|able|ability|abilities)$", r"aliz"),
-        ortho_insert!(r"^(.+)al$", r"^is(e|ed|es|ing|er|ers|ation|ations|m|ms|able|ability|abilities)$", r"alis"),
-        // spectacular +ization = spectacularization (*spectacularrization)
-        ortho_insert!(r"^(.+)ar$", r"^iz(e|ed|es|ing|er|ers|ation|ations|m|ms)$", r"ariz"),
-        ortho_insert!(r"^(.+)ar$", r"^is(e|ed|es|ing|er|ers|ation|ations|m|ms)$", r"aris"),
-
-        // category +ize/+ise = categorize/categorise (*categoryize/*categoryise)
-        // custom +izable/+isable = customizable/customisable (*custommizable/*custommisable)
-        // fantasy +ize = fantasize (*fantasyize)
-        ortho_insert!(r"^(.*[lmnty])$", r"^iz(e|es|ing|ed|er|ers|ation|ations|m|ms|able|ability|abilities)$", r"iz"),
-        ortho_insert!(r"^(.*[lmnty])$", r"^is(e|es|ing|ed|er|ers|ation|ations|m|ms|able|ability|abilities)$", r"is"),
-
-        // criminal + ology = criminology
-        // criminal + ologist = criminalogist (*criminallologist)
-        ortho_insert!(r"^(.+)al$", r"^olog(y|ist|ists|ical|ically)$", r"olog"),
-
-        // similar +ish = similarish (*similarrish)
-        ortho!(r"^(.+)(ar|er|or)$", r"^ish$",
-            vec![ ReplaceItem::WordCapture(1), ReplaceItem::WordCapture(2), ReplaceItem::String("ish".to_string()) ]),
-
-        // free + ed = freed
-        ortho!(r"^(.+e)e$", r"^(e.+)$"),
-        // narrate + ing = narrating (silent e)
-        ortho!(r"^(.+[bcdfghjklmnpqrstuvwxz])e$", r"^([aeiouy].*)$"),
-
-        // defer + ed = deferred (consonant doubling)   XXX monitor(stress not on last syllable)
-        ortho!(r"^(.*(?:[bcdfghjklmnprstvwxyz]|qu)[aeiou])([bcdfgklmnprtvz])$", r"^([aeiouy].*)$",
-            vec![ ReplaceItem::WordCapture(1), ReplaceItem::WordCapture(2), ReplaceItem::WordCapture(2), ReplaceItem::SuffixCapture(1) ]),
+// Parse a "word regex<TAB>suffix regex<TAB>replacement" line.
+fn parse_rule(line: &str) -> Result<Rule, Box<dyn Error>> {
+    let parts: Vec<&str> = line.split('\t').collect();
+    if parts.len() != 3 {
+        return Err("expected a word regex, suffix regex and replacement".into());
+    }
+    let word = Regex::new(parts[0])?;
+    let suffix = Regex::new(parts[1])?;
+    let replace = parse_replace(parts[2])?;
+
+    for item in &replace {
+        match item {
+            ReplaceItem::WordCapture(i) if *i >= word.captures_len() => {
+                return Err(format!("word regex has no group {}", i).into())
+            }
+            ReplaceItem::SuffixCapture(i) if *i >= suffix.captures_len() => {
+                return Err(format!("suffix regex has no group {}", i).into())
+            }
+            _ => {}
+        }
+    }
+
+    Ok(Rule {
+        word,
+        suffix,
+        replace,
+    })
+}
+
+// $1-$9 are word captures, $s1-$s9 are suffix captures, and $$ is a dollar sign.
+fn parse_replace(s: &str) -> Result<Vec<ReplaceItem>, Box<dyn Error>> {
+    let mut items = vec![];
+    let mut text = String::new();
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '$' {
+            text.push(c);
+            continue;
+        }
+        let item = match chars.next() {
+            Some('$') => {
+                text.push('$');
+                continue;
+            }
+            Some('s') => chars
+                .next()
+                .and_then(|x| x.to_digit(10))
+                .map(|x| ReplaceItem::SuffixCapture(x as usize)),
+            Some(x) => x.to_digit(10).map(|x| ReplaceItem::WordCapture(x as usize)),
+            None => None,
+        };
+        match item {
+            Some(i) => {
+                if !text.is_empty() {
+                    items.push(ReplaceItem::String(text.clone()));
+                    text.clear();
+                }
+                items.push(i);
+            }
+            None => return Err(format!("invalid replacement \"{}\"", s).into()),
+        }
+    }
+    if !text.is_empty() {
+        items.push(ReplaceItem::String(text));
+    }
+    Ok(items)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Rules and exceptions only, so the word list can't hide a bad rule.
+    fn rules_only() -> Orthography {
+        Orthography::new(ENGLISH_RULES, "", ENGLISH_EXCEPTIONS).unwrap()
+    }
+
+    fn check(orthography: &Orthography, cases: &[(&str, &str, &str)]) {
+        for (word, suffix, expected) in cases {
+            assert_eq!(
+                orthography.apply(word, suffix),
+                *expected,
+                "{} + {}",
+                word,
+                suffix
+            );
+        }
+    }
 
+    const CASES: [(&str, &str, &str); 40] = [
+        ("artistic", "ly", "artistically"),
+        ("questionable", "ly", "questionably"),
+        ("statute", "ry", "statutory"),
+        ("confirm", "tory", "confirmatory"),
+        ("supervise", "ary", "supervisory"),
+        ("frequent", "cy", "frequency"),
+        ("establish", "s", "establishes"),
+        ("speech", "s", "speeches"),
+        ("search", "s", "searches"),
+        ("cherry", "s", "cherries"),
+        ("beauty", "ful", "beautiful"),
+        ("write", "en", "written"),
+        ("ceremony", "ial", "ceremonial"),
+        ("fantastic", "ical", "fantastical"),
+        ("fantastic", "al", "fantastical"),
+        ("complementary", "ity", "complementarity"),
+        ("perform", "tive", "performative"),
+        ("token", "ize", "tokenize"),
+        ("category", "ize", "categorize"),
+        ("similar", "ish", "similarish"),
+        ("free", "ed", "freed"),
+        ("narrate", "ing", "narrating"),
+        ("die", "ing", "dying"),
+        // Consonant doubling
+        ("defer", "ed", "deferred"),
+        ("refer", "ing", "referring"),
+        ("deter", "ed", "deterred"),
+        ("commit", "ed", "committed"),
+        ("omit", "ing", "omitting"),
+        ("regret", "ed", "regretted"),
+        ("abhor", "ed", "abhorred"),
+        ("stop", "ing", "stopping"),
+        // Unstressed final syllables
+        ("monitor", "ed", "monitored"),
+        ("visit", "ing", "visiting"),
+        ("benefit", "ed", "benefited"),
+        ("limit", "ed", "limited"),
+        ("offer", "ed", "offered"),
+        ("open", "ing", "opening"),
+        ("target", "ed", "targeted"),
+        // Exceptions
+        ("develop", "ed", "developed"),
+        ("travel", "ing", "traveling"),
     ];
+
+    #[test]
+    fn rules() {
+        check(&rules_only(), &CASES);
+    }
+
+    #[test]
+    fn english() {
+        check(&Orthography::english(), &CASES);
+        check(
+            &Orthography::english(),
+            &[
+                ("cancel", "ed", "canceled"),
+                ("test", "ing", "testing"),
+                ("be", "ing", "being"),
+                ("dye", "ing", "dyeing"),
+                ("dye", "ed", "dyed"),
+                ("singe", "ing", "singeing"),
+                ("singe", "ed", "singed"),
+                ("cancel", "ation", "cancellation"),
+                ("cry", "s", "cries"),
+                ("deny", "s", "denies"),
+            ],
+        );
+    }
+
+    #[test]
+    fn candidates() {
+        let rules = "^(.+)y$\t^s$\t$1ies\n^(.+)$\t^s$\t$1zz\n";
+        let o = Orthography::new(rules, "", "").unwrap();
+        // No known words, so the first rule wins
+        assert_eq!(o.apply("cherry", "s"), "cherries");
+
+        // A known rule output beats the plain join, even a more frequent one
+        let o = Orthography::new(rules, "cherrys\ncherryzz", "").unwrap();
+        assert_eq!(o.apply("cherry", "s"), "cherryzz");
+        let o = Orthography::new(rules, "cherryzz\t10\ncherrys\t20", "").unwrap();
+        assert_eq!(o.apply("cherry", "s"), "cherryzz");
+
+        // A known plain join beats an unknown rule output
+        let o = Orthography::new(rules, "cherrys", "").unwrap();
+        assert_eq!(o.apply("cherry", "s"), "cherrys");
+
+        // The most frequent rule output wins, and the earlier rule on a tie
+        let o = Orthography::new(rules, "cherries\t10\ncherryzz\t15", "").unwrap();
+        assert_eq!(o.apply("cherry", "s"), "cherryzz");
+        let o = Orthography::new(rules, "cherries\ncherryzz", "").unwrap();
+        assert_eq!(o.apply("cherry", "s"), "cherries");
+
+        // Exceptions only apply to their suffixes
+        let o = Orthography::new(rules, "", "cherry\tz s").unwrap();
+        assert_eq!(o.apply("cherry", "s"), "cherrys");
+        let o = Orthography::new(rules, "", "cherry\tz").unwrap();
+        assert_eq!(o.apply("cherry", "s"), "cherries");
+    }
+
+    #[test]
+    fn english_words() {
+        let words = parse_frequencies(ENGLISH_WORDS);
+        for word in ["dieing", "stoping", "stoped", "quiting", "traped"] {
+            assert!(!words.contains_key(word), "{}", word);
+        }
+
+        // Both outputs are known words, so the counts pick over rule order
+        let rules = "^(.+)$\t^ing$\t$1ing\n^(.+[aeiou])([np])$\t^ing$\t$1$2$2ing\n";
+        let o = Orthography::new(rules, ENGLISH_WORDS, "").unwrap();
+        check(
+            &o,
+            &[
+                ("plan", "ing", "planning"),
+                ("pin", "ing", "pinning"),
+                ("open", "ing", "opening"),
+            ],
+        );
+    }
+
+    #[test]
+    fn invalid_rules() {
+        assert!(Orthography::new("^(a$\t^b$\t$1", "", "").is_err());
+        assert!(Orthography::new("^(a)$\t^b$\t$2", "", "").is_err());
+        assert!(Orthography::new("^(a)$\t^b$", "", "").is_err());
+        assert!(Orthography::new("^(a)$\t^(b)$\t$s1$$", "", "").is_ok());
+        assert!(Orthography::new("", "", "cancel").is_err());
+    }
 }
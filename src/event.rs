@@ -0,0 +1,47 @@
+/*
+    JSON for the engine's events, which the control API sends subscribers and
+    the WebSocket feed broadcasts.
+*/
+
+use crate::tape::Entry;
+use crate::Ui;
+use moreover::{engine, Outcome};
+use serde_json::{json, Value};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Milliseconds since the Unix epoch.
+pub fn millis(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
+
+// The JSON sent to subscribers for a message, if it's one they're sent.
+pub fn event(msg: &Ui) -> Option<Value> {
+    Some(match msg {
+        Ui::Stroke(s) => {
+            let entry = Entry::new(s);
+            let outcome = match s.outcome {
+                Outcome::Translated(_) => "translated",
+                Outcome::Untranslated => "untranslated",
+                Outcome::Undo => "undo",
+                Outcome::Ignored => "ignored",
+            };
+            json!({
+                "event": "stroke",
+                "time": millis(s.time),
+                "stroke": engine::id_to_compact_steno(s.stroke),
+                "outcome": outcome,
+                "translation": entry.translation,
+                "dictionary": entry.source,
+                "deleted": s.deleted,
+                "added": s.added,
+            })
+        }
+        Ui::Machine(port) => json!({ "event": "machine", "port": port }),
+        Ui::Profile(name) => json!({ "event": "profile", "name": name }),
+        Ui::Output(enabled) => json!({ "event": "output", "enabled": enabled }),
+        Ui::Error(message) => json!({ "event": "error", "message": message }),
+        _ => return None,
+    })
+}
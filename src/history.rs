@@ -0,0 +1,92 @@
+/*
+    Log of every stroke, kept in the data directory with a file per day.
+    Only the most recent days are kept.
+*/
+
+use crate::tape::{Entry, TapeFile};
+use chrono::{Local, NaiveDate};
+use directories::ProjectDirs;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+const KEEP_DAYS: usize = 365;
+const DATE_FORMAT: &str = "%Y-%m-%d";
+
+pub fn dir() -> PathBuf {
+    let proj_dirs = ProjectDirs::from("", "", "moreover").unwrap();
+    proj_dirs.data_dir().join("log")
+}
+
+pub struct StrokeLog {
+    dir: PathBuf,
+    day: NaiveDate,
+    file: TapeFile,
+}
+impl StrokeLog {
+    // Open today's log, removing the ones that are too old.
+    pub fn open() -> io::Result<Self> {
+        let dir = dir();
+        fs::create_dir_all(&dir)?;
+        let day = Local::now().date_naive();
+        let file = TapeFile::open(dir.join(file_name(day)))?;
+        let log = Self { dir, day, file };
+        log.prune()?;
+        Ok(log)
+    }
+
+    // Add an entry, starting a new file when the day changes.
+    pub fn write(&mut self, entry: &Entry) -> io::Result<()> {
+        let day = entry.time.date_naive();
+        if day != self.day {
+            self.file = TapeFile::open(self.dir.join(file_name(day)))?;
+            self.day = day;
+            self.prune()?;
+        }
+        self.file.write(entry)
+    }
+
+    fn prune(&self) -> io::Result<()> {
+        let days = days(&self.dir)?;
+        for day in &days[..days.len().saturating_sub(KEEP_DAYS)] {
+            fs::remove_file(self.dir.join(file_name(*day)))?;
+        }
+        Ok(())
+    }
+}
+
+fn file_name(day: NaiveDate) -> String {
+    format!("{}.log", day.format(DATE_FORMAT))
+}
+
+// Days with a log, oldest first.
+fn days(dir: &PathBuf) -> io::Result<Vec<NaiveDate>> {
+    let mut days: Vec<NaiveDate> = fs::read_dir(dir)?
+        .filter_map(|x| x.ok())
+        .filter_map(|x| {
+            let name = x.file_name().to_string_lossy().to_string();
+            NaiveDate::parse_from_str(name.strip_suffix(".log")?, DATE_FORMAT).ok()
+        })
+        .collect();
+    days.sort();
+    Ok(days)
+}
+
+// The entries of the last few days that have a log, oldest first.
+// Lines that can't be read are skipped.
+pub fn read(last: usize) -> io::Result<Vec<(NaiveDate, Vec<Entry>)>> {
+    let dir = dir();
+    if !dir.is_dir() {
+        return Ok(vec![]);
+    }
+    let days = days(&dir)?;
+    let mut logs = vec![];
+    for day in &days[days.len().saturating_sub(last)..] {
+        let entries = fs::read_to_string(dir.join(file_name(*day)))?
+            .lines()
+            .filter_map(Entry::parse)
+            .collect();
+        logs.push((*day, entries));
+    }
+    Ok(logs)
+}
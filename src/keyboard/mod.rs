@@ -0,0 +1,128 @@
+/*
+    Struct to send engine output to the focused window.
+    Text is normally typed out key by key, but long or non-ASCII text can
+    instead be pasted through the clipboard.
+*/
+
+use crate::engine::Action;
+use arboard::Clipboard;
+use enigo::{Enigo, Key, KeyboardControllable};
+use std::{thread, time::Duration};
+
+// How long to wait for the focused application to read the clipboard before
+// the previous contents are put back.
+const PASTE_DELAY: Duration = Duration::from_millis(50);
+pub const DEFAULT_PASTE_LENGTH: usize = 20;
+
+pub struct Keyboard {
+    enigo: Enigo,
+    clipboard: Option<Clipboard>,
+    paste_length: Option<usize>,
+}
+impl Keyboard {
+    // `paste_length` is the length at which text is pasted instead of typed.
+    // Non-ASCII text is always pasted if it is set, and never if it is None.
+    pub fn new(paste_length: Option<usize>) -> Self {
+        Self {
+            enigo: Enigo::new(),
+            clipboard: paste_length.and_then(|_| Clipboard::new().ok()),
+            paste_length,
+        }
+    }
+
+    // Undo the deleted actions and perform the added ones.
+    pub fn send(&mut self, del: &[Action], add: &[Action]) {
+        // Every deleted character takes one backspace, control characters included.
+        for a in del {
+            if let Action::Text(s) = a {
+                for _ in s.chars() {
+                    self.enigo.key_click(Key::Backspace);
+                }
+            }
+        }
+
+        // Text is collected into runs, so that a whole run can be pasted at once.
+        let mut run = vec![];
+        for a in add {
+            match a {
+                Action::Text(s) => run.push(s.as_str()),
+                // Commands are for the engine's host, not the focused window
+                Action::Command(_) => {}
+                _ => {
+                    self.send_text(&run.concat());
+                    run.clear();
+                    match a {
+                        Action::KeyClick(k) => self.enigo.key_click(*k),
+                        Action::KeyUp(k) => self.enigo.key_up(*k),
+                        Action::KeyDown(k) => self.enigo.key_down(*k),
+                        Action::Text(_) | Action::Command(_) => unreachable!(),
+                    }
+                }
+            }
+        }
+        self.send_text(&run.concat());
+    }
+
+    fn send_text(&mut self, text: &str) {
+        if text.is_empty() {
+            return;
+        }
+        if self.should_paste(text) && self.paste(text) {
+            return;
+        }
+
+        // key_sequence doesn't handle control characters, so they're sent as
+        // their own keys in between the rest of the text.
+        let mut rest = text;
+        while let Some(i) = rest.find(|c| control_key(c).is_some()) {
+            let c = rest[i..].chars().next().unwrap();
+            if i > 0 {
+                self.enigo.key_sequence(&rest[..i]);
+            }
+            self.enigo.key_click(control_key(c).unwrap());
+            rest = &rest[i + c.len_utf8()..];
+        }
+        if !rest.is_empty() {
+            self.enigo.key_sequence(rest);
+        }
+    }
+
+    fn should_paste(&self, text: &str) -> bool {
+        match self.paste_length {
+            Some(n) => text.chars().count() >= n || !text.is_ascii(),
+            None => false,
+        }
+    }
+
+    // Put the text on the clipboard, paste it, and restore the old clipboard.
+    // Returns false if the clipboard couldn't be used, so the text can be typed instead.
+    fn paste(&mut self, text: &str) -> bool {
+        let clipboard = match &mut self.clipboard {
+            Some(c) => c,
+            None => return false,
+        };
+        // Only text contents can be restored.
+        let previous = clipboard.get_text().ok();
+        if clipboard.set_text(text).is_err() {
+            return false;
+        }
+
+        self.enigo.key_down(Key::Control);
+        self.enigo.key_click(Key::Layout('v'));
+        self.enigo.key_up(Key::Control);
+
+        thread::sleep(PASTE_DELAY);
+        if let Some(p) = previous {
+            clipboard.set_text(p).ok();
+        }
+        true
+    }
+}
+
+fn control_key(c: char) -> Option<Key> {
+    match c {
+        '\n' => Some(Key::Return),
+        '\t' => Some(Key::Tab),
+        _ => None,
+    }
+}
@@ -0,0 +1,34 @@
+/*
+    moreover, a steno engine.
+
+    The engine translates strokes into actions using dictionaries and the output
+    formatting rules, machines read strokes from steno hardware, and the keyboard
+    sends the resulting actions to the focused window, or a buffer collects them
+    as text. Which window is focused can be watched, to change settings with it.
+    The moreover binary is a terminal display built on top of these.
+*/
+
+pub mod buffer;
+pub mod engine;
+pub mod keyboard;
+pub mod machine;
+pub mod window;
+
+pub use engine::{Action, Engine, Outcome};
+use std::time::SystemTime;
+
+// A stroke read from the machine, and what it did.
+#[derive(Debug, Clone)]
+pub struct StrokeEvent {
+    pub stroke: u32,
+    // When the stroke was read
+    pub time: SystemTime,
+    // Time taken in milliseconds
+    pub duration: u128,
+    // Net number of actions
+    pub actions: i32,
+    pub outcome: Outcome,
+    // Text taken back and typed by the stroke
+    pub deleted: String,
+    pub added: String,
+}
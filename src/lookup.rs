@@ -0,0 +1,137 @@
+/*
+    Looking up dictionary entries from the display, and changing them in the
+    dictionaries that can be written to.
+*/
+
+use moreover::engine::{self, Engine};
+
+// A dictionary's translation of an outline. Rows are in the order of the
+// engine's dictionaries, highest priority first.
+#[derive(Debug, PartialEq)]
+pub struct Row {
+    pub name: String,
+    pub translation: Option<String>,
+    // Whether this is the translation the engine uses, which overrides the
+    // ones below it
+    pub used: bool,
+    pub enabled: bool,
+    pub writable: bool,
+}
+
+// An outline in steno, with its strokes separated by "/".
+pub fn steno(outline: &[u32]) -> String {
+    let strokes: Vec<String> = outline
+        .iter()
+        .map(|x| engine::id_to_compact_steno(*x))
+        .collect();
+    strokes.join("/")
+}
+
+// The translation of the outline in every dictionary.
+pub fn translations(engine: &Engine, outline: &[u32]) -> Vec<Row> {
+    let mut found = false;
+    engine
+        .lookup_all(outline)
+        .into_iter()
+        .zip(engine.dictionaries())
+        .map(|(translation, dict)| {
+            let used = translation.is_some() && dict.is_enabled() && !found;
+            found |= used;
+            Row {
+                name: dict.name().to_string(),
+                translation: translation.map(|x| x.raw().to_string()),
+                used,
+                enabled: dict.is_enabled(),
+                writable: dict.is_writable(),
+            }
+        })
+        .collect()
+}
+
+// The outlines for a word, with the dictionary each one is from.
+pub fn outlines(engine: &Engine, word: &str) -> Vec<(String, String)> {
+    engine
+        .reverse_lookup(word)
+        .into_iter()
+        .map(|outline| {
+            let name = translations(engine, &outline)
+                .into_iter()
+                .find(|x| x.used)
+                .map(|x| x.name)
+                .unwrap_or_default();
+            (steno(&outline), name)
+        })
+        .collect()
+}
+
+// Add, change or remove (with None) an entry, saving the dictionary's file.
+pub fn set(
+    engine: &mut Engine,
+    dictionary: usize,
+    outline: &[u32],
+    translation: Option<&str>,
+) -> Result<(), String> {
+    let dict = engine
+        .dictionary_mut(dictionary)
+        .ok_or("No such dictionary")?;
+    if !dict.is_writable() {
+        return Err(format!("{} can't be written to", dict.name()));
+    }
+    dict.set(outline, translation)
+        .map_err(|e| format!("Couldn't save {}: {}", dict.name(), e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use moreover::engine::dictionary::{Dictionary, TreeDict};
+    use moreover::engine::steno_to_id;
+
+    #[test]
+    fn lookup() {
+        let dict = |s: &str| -> Box<dyn Dictionary + Send> {
+            Box::new(TreeDict::from_reader(s.as_bytes()))
+        };
+        let mut engine = Engine::new();
+        engine.add_dictionary(dict("KAt\tcat\n"));
+        engine.add_dictionary(dict("KAt\tcap\nKAp\tcap\n"));
+
+        let rows = translations(&engine, &[steno_to_id("KAt")]);
+        let found: Vec<(Option<&str>, bool)> = rows
+            .iter()
+            .map(|x| (x.translation.as_deref(), x.used))
+            .collect();
+        // The last dictionary has the highest priority, and numbers are at the bottom
+        assert_eq!(
+            found,
+            vec![(Some("cap"), true), (Some("cat"), false), (None, false)]
+        );
+        assert_eq!(rows[2].name, "numbers");
+
+        assert_eq!(
+            outlines(&engine, "cap"),
+            vec![
+                ("KAp".to_string(), String::new()),
+                ("KAt".to_string(), String::new())
+            ]
+        );
+        // cat is only written by an outline that's overridden
+        assert!(outlines(&engine, "cat").is_empty());
+
+        // Dictionaries that weren't read from a file can't be saved
+        assert!(set(&mut engine, 0, &[steno_to_id("KAt")], None).is_err());
+
+        // A dictionary that's turned off doesn't override the ones below it
+        let id = engine.dictionaries().next().unwrap().id();
+        engine.set_dictionary_enabled(id, false);
+        let rows = translations(&engine, &[steno_to_id("KAt")]);
+        assert_eq!(
+            (rows[0].used, rows[0].enabled, rows[1].used),
+            (false, false, true)
+        );
+        assert_eq!(
+            outlines(&engine, "cat"),
+            vec![("KAt".to_string(), String::new())]
+        );
+    }
+}
@@ -2,67 +2,138 @@
     Struct to represent a Gemini PR machine to read stroke input from.
 */
 
-use super::engine::steno_to_id;
-use super::Ui;
+use crate::engine::parse_steno;
 use serialport::SerialPort;
+use std::sync::{Arc, Mutex};
 use std::{error::Error, io::ErrorKind, thread, time::Duration};
 
 const READ_RATE: Duration = Duration::from_millis(50);
 const RECONNECT_RATE: Duration = Duration::from_millis(1000);
 
+// Names of the Gemini PR keys, in the order of their bits
+pub const GEMINI_KEYS: [&str; 42] = [
+    "Fn", "#1", "#2", "#3", "#4", "#5", "#6", "S1", "S2", "T-", "K-", "P-", "W-", "H-", "R-", "A-",
+    "O-", "*1", "*2", "res1", "res2", "pwr", "*3", "*4", "-E", "-U", "-F", "-R", "-P", "-B", "-L",
+    "-G", "-T", "-S", "-D", "#7", "#8", "#9", "#A", "#B", "#C", "-Z",
+];
+// Steno keys of each Gemini PR key by default, with the function and reserved
+// keys doing nothing
+const GEMINI_DEFAULT: [&str; 42] = [
+    "", "#", "#", "#", "#", "#", "#", "S", "S", "T", "K", "P", "W", "H", "R", "A", "O", "*", "*",
+    "", "", "", "*", "*", "e", "u", "f", "r", "p", "b", "l", "g", "t", "s", "d", "#", "#", "#",
+    "#", "#", "#", "z",
+];
+
+// What pressing a machine key does.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Mapping {
+    // Steno keys added to the stroke, possibly none
+    Keys(u32),
+    // A whole stroke sent instead of the rest of the chord
+    Stroke(u32),
+}
+impl Mapping {
+    // Parse steno keys, where an empty string is no key at all.
+    pub fn keys(s: &str) -> Option<Self> {
+        parse_steno(s).map(Mapping::Keys)
+    }
+    pub fn stroke(s: &str) -> Option<Self> {
+        parse_steno(s).map(Mapping::Stroke)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Keymap([Mapping; 42]);
+impl Default for Keymap {
+    fn default() -> Self {
+        Self(GEMINI_DEFAULT.map(|x| Mapping::keys(x).unwrap()))
+    }
+}
+impl Keymap {
+    // Keymap from the mappings of every key, in the order of their bits.
+    pub fn new(mappings: [Mapping; 42]) -> Self {
+        Self(mappings)
+    }
+
+    // Change what a key does, by its name in GEMINI_KEYS.
+    pub fn set(&mut self, key: &str, mapping: Mapping) -> Result<(), String> {
+        let i = GEMINI_KEYS
+            .iter()
+            .position(|x| *x == key)
+            .ok_or_else(|| format!("Unknown machine key: {}", key))?;
+        self.0[i] = mapping;
+        Ok(())
+    }
+
+    // Turn a Gemini PR packet into a stroke.
+    fn stroke(&self, packet: &[u8]) -> u32 {
+        let mut stroke = 0;
+        let mut replacement = None;
+        for (byte, e) in packet.iter().enumerate() {
+            for i in (0..7).rev() {
+                if e & (1 << i) != 0 {
+                    match self.0[7 * byte + 6 - i] {
+                        Mapping::Keys(k) => stroke |= k,
+                        Mapping::Stroke(s) => replacement = Some(s),
+                    }
+                }
+            }
+        }
+        replacement.unwrap_or(stroke)
+    }
+}
+
 pub struct Machine {
     port: Box<dyn SerialPort>,
     path: String,
-    keymap: [char; 42],
+    // Shared, so that it can be changed while another thread is reading
+    keymap: Arc<Mutex<Keymap>>,
 }
 impl Machine {
-    pub fn new(path: String, keymap: [char; 42]) -> Self {
+    pub fn new(path: String, keymap: Keymap) -> Result<Self, serialport::Error> {
         let port = serialport::new(&path, 9600)
             .timeout(Duration::from_millis(10))
-            .open()
-            .expect("Failed to open port");
+            .open()?;
 
-        Self {
-            port: port,
-            path: path,
-            keymap: keymap,
-        }
+        Ok(Self {
+            port,
+            path,
+            keymap: Arc::new(Mutex::new(keymap)),
+        })
+    }
+
+    pub fn set_keymap(&mut self, keymap: Keymap) {
+        *self.keymap.lock().unwrap() = keymap;
+    }
+    // The keymap, for changing it after the machine has been moved to the
+    // thread reading from it.
+    pub fn keymap(&self) -> Arc<Mutex<Keymap>> {
+        self.keymap.clone()
     }
 
-    // Read a stroke from the serial buffer, and return the processed steno version of it
-    pub fn read(&mut self, tx: std::sync::mpsc::Sender<Ui>) -> Result<u32, Box<dyn Error>> {
+    // Read a stroke from the serial buffer, and return the processed steno version of it.
+    // If the machine is unplugged, this waits for it to come back, and tells the
+    // callback when it's disconnected and reconnected.
+    pub fn read(&mut self, mut connected: impl FnMut(bool)) -> Result<u32, Box<dyn Error>> {
         let mut buffer: Vec<u8> = vec![0; 6];
         loop {
             match self.port.read_exact(buffer.as_mut_slice()) {
                 Ok(()) => {
                     if buffer[0] & 0b1000_0000 > 0 {
-                        let mut stroke = String::with_capacity(super::engine::STENO_ORDER.len());
-                        for (byte, e) in buffer.iter().enumerate() {
-                            for i in (0..7).rev() {
-                                let mask = 1 << i;
-                                let index = 7 * byte + 6 - i;
-                                if e & mask != 0 {
-                                    let key = self.keymap[index];
-                                    if !stroke.contains(key) {
-                                        stroke.push(key);
-                                    }
-                                }
-                            }
-                        }
-                        return Ok(steno_to_id(&stroke));
+                        return Ok(self.keymap.lock().unwrap().stroke(&buffer));
                     }
                 }
                 Err(e) => match e.kind() {
                     ErrorKind::TimedOut => {}
                     ErrorKind::BrokenPipe => loop {
-                        tx.send(Ui::Machine(String::new())).unwrap();
+                        connected(false);
                         let port = serialport::new(&self.path, 9600)
                             .timeout(Duration::from_millis(10))
                             .open();
                         match port {
                             Ok(x) => {
                                 self.port = x;
-                                tx.send(Ui::Machine(self.path.clone())).unwrap();
+                                connected(true);
                                 break;
                             }
                             Err(_) => thread::sleep(RECONNECT_RATE),
@@ -75,3 +146,29 @@ impl Machine {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::steno_to_id;
+
+    #[test]
+    fn packets() {
+        let mut keymap = Keymap::default();
+        // S1, A-, -T
+        let packet = [0b1000_0000, 0b0100_0000, 0b0010_0000, 0, 0b0000_0100, 0];
+        assert_eq!(keymap.stroke(&packet), steno_to_id("SAt"));
+        // #1 and #C
+        let packet = [0b1010_0000, 0, 0, 0, 0, 0b0000_0010];
+        assert_eq!(keymap.stroke(&packet), steno_to_id("#"));
+        // Fn does nothing by default
+        let packet = [0b1100_0000, 0, 0, 0, 0, 0];
+        assert_eq!(keymap.stroke(&packet), 0);
+
+        // Fn replaces the rest of the chord
+        keymap.set("Fn", Mapping::stroke("*").unwrap()).unwrap();
+        let packet = [0b1100_0001, 0, 0, 0, 0, 0];
+        assert_eq!(keymap.stroke(&packet), steno_to_id("*"));
+        assert!(keymap.set("Fn2", Mapping::Keys(0)).is_err());
+    }
+}
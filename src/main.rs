@@ -1,257 +1,663 @@
-mod engine;
-mod machine;
-
-use crossterm::event::Event;
-use crossterm::style::{Print, Stylize};
-use crossterm::{cursor, event, execute, queue, terminal};
-use directories::ProjectDirs;
-use engine::Action;
-use enigo::{Enigo, Key, KeyboardControllable};
-use std::io::{stdout, Write};
-use std::{collections::VecDeque, fs, sync::mpsc};
-use toml::Value;
+mod config;
+#[cfg(unix)]
+mod control;
+mod diagram;
+mod drill;
+mod event;
+mod history;
+mod lookup;
+mod review;
+mod speed;
+mod stats;
+mod tape;
+mod transcript;
+mod tui;
+mod websocket;
 
-pub enum Ui {
-    Stroke(u32, u128, i32),
-    Machine(String),
-    DictionaryLoaded,
-    Resize(u16, u16),
+use clap::{Args, Parser, Subcommand};
+use config::Config;
+use history::StrokeLog;
+use moreover::buffer::{actions_text, Buffer, Transcript};
+use moreover::engine::dictionary::{Dictionary, TreeDict};
+use moreover::window::{self, Window};
+use moreover::{engine, keyboard, machine, Action, StrokeEvent};
+use stats::Stats;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+use std::{fs, sync::mpsc};
+use tape::{Entry, TapeFile};
+use transcript::TranscriptFile;
+
+/// Steno engine
+#[derive(Parser)]
+#[command(version)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Read strokes from the machine and type them out (the default)
+    Run(RunArgs),
+    /// Show the outlines that translate to a word
+    Lookup {
+        word: String,
+        #[command(flatten)]
+        config: ConfigArgs,
+    },
+    /// Translate a file of strokes and print the resulting text
+    Translate {
+        file: PathBuf,
+        #[command(flatten)]
+        config: ConfigArgs,
+    },
+    /// Convert a Plover JSON dictionary to moreover's format
+    Convert { input: PathBuf, output: PathBuf },
+    /// List the serial ports a machine can be connected to
+    Ports,
+    /// Summarize the stroke log of the last few days
+    Stats {
+        /// Number of days with strokes to include
+        #[arg(long, default_value_t = 7)]
+        days: usize,
+    },
+}
+
+#[derive(Args, Default)]
+struct ConfigArgs {
+    /// Config file to use instead of the default one
+    #[arg(long, value_name = "PATH")]
+    config: Option<PathBuf>,
+    /// Dictionary to use instead of the configured ones, highest priority last
+    #[arg(long = "dict", value_name = "PATH")]
+    dictionaries: Vec<String>,
+}
+
+#[derive(Args, Default)]
+struct RunArgs {
+    #[command(flatten)]
+    config: ConfigArgs,
+    /// Serial port of the machine to use instead of the configured one
+    #[arg(long, value_name = "PORT")]
+    machine: Option<String>,
+    /// Run without the terminal display
+    #[arg(long)]
+    headless: bool,
 }
 
-const DISPLAY_LEN: u16 = 25;
+fn main() {
+    let cli = Cli::parse();
+    let result = match cli.command.unwrap_or(Command::Run(RunArgs::default())) {
+        Command::Run(args) => run(args),
+        Command::Lookup { word, config } => lookup(&word, &config),
+        Command::Translate { file, config } => translate(&file, &config),
+        Command::Convert { input, output } => convert(&input, &output),
+        Command::Ports => ports(),
+        Command::Stats { days } => stats(days),
+    };
+    if let Err(e) = result {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+}
 
-fn main() -> Result<(), std::io::Error> {
+fn run(args: RunArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let (config, problems) = match read_config(&args.config) {
+        Ok(mut config) => {
+            if let Some(port) = args.machine {
+                config.machine = Some(port);
+            }
+            let problems = config.problems(true);
+            (config, problems)
+        }
+        Err(e) => (Config::default(), vec![e]),
+    };
+    if args.headless && !problems.is_empty() {
+        return Err(problems.join("\n").into());
+    }
+
+    // Messages from the engine go to the display, the control API and the
+    // WebSocket feed
     let (tx, rx) = mpsc::channel();
-    let tx1 = tx.clone(); // otherwise the main thread will end after panic
-    std::thread::spawn(move || {
-        steno_loop(tx);
-    });
+    let (display_tx, display_rx) = mpsc::channel();
+    let mut listeners = vec![display_tx.clone()];
+    let (input_tx, input_rx) = mpsc::channel();
+    let engine = Arc::new(Mutex::new(engine::Engine::new()));
+    let transcript = Arc::new(Mutex::new(Transcript::new()));
+    let drill = config.drill.clone();
+    #[cfg(unix)]
+    if problems.is_empty() && config.control.enabled {
+        let path = config
+            .control
+            .socket
+            .clone()
+            .map_or_else(control::default_path, PathBuf::from);
+        let profiles = config.profiles.keys().cloned().collect();
+        match control::start(
+            &path,
+            engine.clone(),
+            transcript.clone(),
+            input_tx.clone(),
+            tx.clone(),
+            profiles,
+        ) {
+            Ok(events) => listeners.push(events),
+            Err(e) => tx
+                .send(Ui::Error(format!("Could not start the control API: {}", e)))
+                .unwrap(),
+        }
+    }
+    if problems.is_empty() && config.websocket.enabled {
+        match websocket::start(&config.websocket.address) {
+            Ok(strokes) => listeners.push(strokes),
+            Err(e) => tx
+                .send(Ui::Error(format!(
+                    "Could not listen on {}: {}",
+                    config.websocket.address, e
+                )))
+                .unwrap(),
+        }
+    }
     std::thread::spawn(move || {
-        event_loop(tx1).unwrap();
+        for msg in rx {
+            for listener in &listeners {
+                listener.send(msg.clone()).ok();
+            }
+        }
     });
+    if problems.is_empty() {
+        let engine = engine.clone();
+        std::thread::spawn(move || {
+            steno_loop(tx, config, engine, transcript, input_tx, input_rx);
+        });
+    } else {
+        for problem in problems {
+            tx.send(Ui::Error(problem)).unwrap();
+        }
+    }
 
-    let mut stdout = stdout();
-    let mut dim = terminal::size()?;
-    execute!(stdout, terminal::Clear(terminal::ClearType::All))?;
-
-    let mut display_buffer = VecDeque::new();
-    let mut times_buffer = VecDeque::new();
-    let mut efficiency_buffer = VecDeque::new();
-    let mut dicts = 0;
-    let mut machine_status = String::new();
-
-    draw_dict_status(&mut stdout, dim, dicts)?;
-    draw_machine_status(&mut stdout, dim, None)?;
-    draw_stroke_display(&mut stdout, dim, &display_buffer, 0, 0.0, 0.0)?;
-    stdout.flush()?;
-
-    while let Ok(msg) = rx.recv() {
-        match msg {
-            Ui::Stroke(s, d, n) => {
-                display_buffer.push_back(s);
-                if d < 2500 {
-                    times_buffer.push_back(d);
-                }
-                efficiency_buffer.push_back(n);
-                if display_buffer.len() > DISPLAY_LEN.into() {
-                    display_buffer.pop_front();
-                    efficiency_buffer.pop_front();
-                }
-                if times_buffer.len() > DISPLAY_LEN.into() {
-                    times_buffer.pop_front();
-                }
-                let avg =
-                    1000.0 / (times_buffer.iter().sum::<u128>() as f64 / times_buffer.len() as f64);
-                let efficiency =
-                    efficiency_buffer.iter().sum::<i32>() as f64 / times_buffer.len() as f64;
-                draw_stroke_display(&mut stdout, dim, &display_buffer, d, avg, efficiency)?;
-            }
-            Ui::Machine(s) => {
-                machine_status.clear();
-                machine_status.push_str(&s);
-                if s.len() > 0 {
-                    draw_machine_status(&mut stdout, dim, Some(s))?
-                } else {
-                    draw_machine_status(&mut stdout, dim, None)?
+    if args.headless {
+        drop(display_tx);
+        let mut failed = false;
+        while let Ok(msg) = display_rx.recv() {
+            match msg {
+                Ui::Machine(s) if s.is_empty() => eprintln!("Machine disconnected"),
+                Ui::Machine(s) => eprintln!("Connected to {}", s),
+                Ui::Profile(s) => eprintln!("Switched to profile {}", s),
+                Ui::Output(on) => eprintln!("Output {}", if on { "on" } else { "off" }),
+                Ui::Error(e) => {
+                    eprintln!("{}", e);
+                    failed = true;
                 }
+                _ => {}
             }
-            Ui::DictionaryLoaded => {
-                dicts += 1;
-                draw_dict_status(&mut stdout, dim, dicts)?;
-            }
-            Ui::Resize(w, h) => {
-                dim = (w, h);
-                execute!(stdout, terminal::Clear(terminal::ClearType::All))?;
-                draw_dict_status(&mut stdout, dim, dicts)?;
-                draw_machine_status(&mut stdout, dim, Some(machine_status.clone()))?;
-                draw_stroke_display(&mut stdout, dim, &display_buffer, 0, 0.0, 0.0)?;
-            }
         }
-        stdout.flush()?;
+        if failed {
+            std::process::exit(1);
+        }
+    } else {
+        // The display's own messages keep it running if the engine stops
+        tui::run(display_tx, display_rx, engine, drill)?;
     }
     Ok(())
 }
 
-fn event_loop(tx: mpsc::Sender<Ui>) -> crossterm::Result<()> {
-    loop {
-        match event::read()? {
-            Event::Resize(w, h) => tx.send(Ui::Resize(w, h)).unwrap(),
-            _ => {}
-        }
+fn lookup(word: &str, args: &ConfigArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let engine = load_engine(&read_checked_config(args)?, None)?;
+    let outlines = engine.reverse_lookup(word);
+    if outlines.is_empty() {
+        return Err(format!("No outlines found for \"{}\"", word).into());
+    }
+    for outline in outlines {
+        println!("{}", lookup::steno(&outline));
+    }
+    Ok(())
+}
+
+fn convert(input: &Path, output: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    let json = fs::read_to_string(input)?;
+    let (dict, skipped) = engine::convert::from_plover_json(&json)?;
+    fs::write(output, dict)?;
+    if skipped > 0 {
+        eprintln!("Skipped {} entries with unsupported keys", skipped);
     }
+    Ok(())
 }
 
-fn steno_loop(tx: mpsc::Sender<Ui>) {
-    let proj_dirs = ProjectDirs::from("", "", "moreover").unwrap();
-    let file = proj_dirs.config_dir().join("moreover.toml");
-    let config = fs::read_to_string(file)
-        .expect("Could not read config file")
-        .parse::<Value>()
-        .unwrap();
+fn ports() -> Result<(), Box<dyn std::error::Error>> {
+    for port in serialport::available_ports()? {
+        println!("{}", port.port_name);
+    }
+    Ok(())
+}
 
-    let mut engine = engine::Engine::new();
-    for dict in config["dictionaries"].as_array().unwrap() {
-        tx.send(Ui::DictionaryLoaded).unwrap();
-        engine.add_dict(dict.as_str().unwrap());
+fn stats(days: usize) -> Result<(), Box<dyn std::error::Error>> {
+    let logs = history::read(days)?;
+    if logs.is_empty() {
+        return Err(format!("No strokes logged in {}", history::dir().display()).into());
     }
-    let mut machine = machine::Machine::new(
-        config["machine"].as_str().unwrap().to_string(),
-        config["keymap"]
-            .as_array()
-            .unwrap()
-            .iter()
-            .map(|x| x.as_str().unwrap().chars().next().unwrap_or_default())
-            .collect::<Vec<char>>()
-            .try_into()
-            .unwrap(),
+
+    println!(
+        "{:<10}  {:>7}  {:>6}  {:>5}  {:>12}  {:>6}",
+        "day", "strokes", "words", "wpm", "strokes/word", "undos"
     );
-    tx.send(Ui::Machine(config["machine"].as_str().unwrap().to_string()))
-        .unwrap();
-    let mut enigo = Enigo::new();
+    let mut total = Stats::new();
+    for (day, entries) in &logs {
+        let mut stats = Stats::new();
+        for entry in entries {
+            stats.add(entry);
+            total.add(entry);
+        }
+        print_stats(&day.to_string(), &stats);
+    }
+    print_stats("total", &total);
 
-    let mut time_start;
+    let misstrokes = total.misstrokes(10);
+    if !misstrokes.is_empty() {
+        println!("\nMost misstroked:");
+        for (outline, count) in misstrokes {
+            println!("{:<10}  {:>7}", outline, count);
+        }
+    }
+    Ok(())
+}
 
-    loop {
-        time_start = std::time::Instant::now();
-        let stroke = machine.read(tx.clone()).expect("Unable to read stroke");
-        if stroke == 0 {
-            continue;
+fn print_stats(label: &str, stats: &Stats) {
+    println!(
+        "{:<10}  {:>7}  {:>6}  {:>5.1}  {:>12.2}  {:>5.1}%",
+        label,
+        stats.strokes(),
+        stats.words(),
+        stats.wpm(),
+        stats.strokes_per_word(),
+        stats.undo_rate() * 100.0
+    );
+}
+
+// Translate a file of strokes, one outline per line, and print the resulting text.
+fn translate(path: &Path, args: &ConfigArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let mut engine = load_engine(&read_checked_config(args)?, None)?;
+    let mut buffer = Buffer::new();
+
+    for (i, line) in fs::read_to_string(path)?.lines().enumerate() {
+        let strokes = engine::parse_outline(line).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}:{}: invalid stroke \"{}\"", path.display(), i + 1, line),
+            )
+        })?;
+        for stroke in strokes {
+            let (del, add) = engine.process_stroke(stroke);
+            buffer.apply(&del, &add);
         }
+    }
+    println!("{}", buffer.text());
+    Ok(())
+}
 
-        let (del, add) = engine.process_stroke(stroke);
+// Read the config file, with the dictionaries given on the command line.
+fn read_config(args: &ConfigArgs) -> Result<Config, String> {
+    let path = args.config.clone().unwrap_or_else(Config::default_path);
+    let mut config = Config::load(&path)?;
+    if !args.dictionaries.is_empty() {
+        config.dictionaries = args.dictionaries.clone();
+    }
+    Ok(config)
+}
 
-        for a in &del {
-            match a {
-                Action::Text(_) => enigo.key_click(Key::Backspace),
-                _ => {}
+// Read the config for commands that don't use the machine, failing with
+// everything that's wrong with it.
+fn read_checked_config(args: &ConfigArgs) -> Result<Config, String> {
+    let config = read_config(args)?;
+    let problems = config.problems(false);
+    if !problems.is_empty() {
+        return Err(problems.join("\n"));
+    }
+    Ok(config)
+}
+
+// Create an engine with the configured dictionaries and orthography.
+fn load_engine(config: &Config, tx: Option<&mpsc::Sender<Ui>>) -> Result<engine::Engine, String> {
+    let mut engine = engine::Engine::new();
+    load_settings(&mut engine, config, tx)?;
+    Ok(engine)
+}
+
+// Replace the engine's dictionaries and orthography with the configured ones.
+// Dictionaries are only read the first time they're used. The engine is left
+// alone if any of them can't be loaded.
+fn load_settings(
+    engine: &mut engine::Engine,
+    config: &Config,
+    tx: Option<&mpsc::Sender<Ui>>,
+) -> Result<(), String> {
+    let mut dictionaries: Vec<(&str, Box<dyn Dictionary + Send>)> = vec![];
+    for dict in &config.dictionaries {
+        if !engine.has_dictionary(dict) && !dictionaries.iter().any(|x| x.0 == dict) {
+            let tree = TreeDict::new(dict)
+                .map_err(|e| format!("Could not load dictionary {}: {}", dict, e))?;
+            dictionaries.push((dict, Box::new(tree)));
+        }
+        if let Some(tx) = tx {
+            tx.send(Ui::DictionaryLoaded).unwrap();
+        }
+    }
+    let orthography = &config.orthography;
+    let orthography = if orthography.rules.is_some()
+        || orthography.words.is_some()
+        || orthography.exceptions.is_some()
+    {
+        Arc::new(
+            engine::Orthography::load(
+                orthography.rules.as_deref(),
+                orthography.words.as_deref(),
+                orthography.exceptions.as_deref(),
+            )
+            .map_err(|e| format!("Could not load orthography: {}", e))?,
+        )
+    } else {
+        engine::Orthography::english()
+    };
+    for (path, dict) in dictionaries {
+        engine.load_dictionary(path, dict);
+    }
+    engine.set_dictionaries(&config.dictionaries);
+    engine.set_orthography(orthography);
+    Ok(())
+}
+
+// Messages sent to whatever is displaying the engine's state.
+#[derive(Clone)]
+pub enum Ui {
+    Stroke(StrokeEvent),
+    // Path of the connected machine, or empty if disconnected
+    Machine(String),
+    DictionaryLoaded,
+    // Name of the profile switched to, before its dictionaries are loaded.
+    // Also sent when the dictionaries change for another window.
+    Profile(String),
+    // Whether output was turned on or off
+    Output(bool),
+    // Something went wrong, like a problem with the config
+    Error(String),
+    Resize(u16, u16),
+    // A key pressed in the terminal
+    Key(crossterm::event::KeyEvent),
+    // Time passing, for what changes without any strokes
+    Tick,
+}
+
+// What the steno loop acts on.
+#[derive(Debug, PartialEq)]
+enum Input {
+    // A stroke from the machine or the control API
+    Stroke(u32),
+    // An engine command, like the ones in dictionary entries
+    Command(String),
+    // The window that was focused
+    Focus(Window),
+}
+
+// Runs until there's no more input: the machine can't be read, and there's no
+// control API. Problems are reported to the display.
+// The engine is shared with the display, and only locked while a stroke is
+// being processed, not while its output is sent.
+fn steno_loop(
+    tx: mpsc::Sender<Ui>,
+    config: Config,
+    engine_lock: Arc<Mutex<engine::Engine>>,
+    transcript: Arc<Mutex<Transcript>>,
+    input_tx: mpsc::Sender<Input>,
+    input: mpsc::Receiver<Input>,
+) {
+    if let Err(e) = load_settings(&mut engine_lock.lock().unwrap(), &config, Some(&tx)) {
+        tx.send(Ui::Error(e)).unwrap();
+        return;
+    }
+    let mut active = Active {
+        profile: config::DEFAULT_PROFILE.to_string(),
+        window: None,
+    };
+    if !config.windows.is_empty() {
+        let input_tx = input_tx.clone();
+        let tx = tx.clone();
+        std::thread::spawn(move || {
+            if let Err(e) = window::watch(|x| input_tx.send(Input::Focus(x)).is_ok()) {
+                tx.send(Ui::Error(format!(
+                    "Unable to watch the focused window: {}",
+                    e
+                )))
+                .unwrap();
+            }
+        });
+    }
+    let port = config.machine.clone().unwrap_or_default();
+    let keymap = config.keymap().unwrap_or_default();
+    // Strokes are read on a thread of their own, so that they can be
+    // interleaved with input from the control API
+    let keymap = match machine::Machine::new(port.clone(), keymap) {
+        Ok(mut machine) => {
+            tx.send(Ui::Machine(port.clone())).unwrap();
+            let keymap = machine.keymap();
+            let tx = tx.clone();
+            std::thread::spawn(move || loop {
+                let connected = |x: bool| {
+                    let port = if x { port.clone() } else { String::new() };
+                    tx.send(Ui::Machine(port)).unwrap();
+                };
+                match machine.read(connected) {
+                    Ok(0) => {}
+                    Ok(stroke) => {
+                        if input_tx.send(Input::Stroke(stroke)).is_err() {
+                            return;
+                        }
+                    }
+                    Err(e) => {
+                        tx.send(Ui::Error(format!("Unable to read stroke: {}", e)))
+                            .unwrap();
+                        return;
+                    }
+                }
+            });
+            Some(keymap)
+        }
+        Err(e) => {
+            tx.send(Ui::Error(format!("Failed to open {}: {}", port, e)))
+                .unwrap();
+            // Only the control API can send strokes now
+            drop(input_tx);
+            None
+        }
+    };
+    let mut keyboard = keyboard::Keyboard::new(config.paste_length());
+    let mut tape = match config.tape.as_deref().map(TapeFile::open) {
+        Some(Ok(file)) => Some(file),
+        Some(Err(e)) => {
+            tx.send(Ui::Error(format!("Could not open the paper tape: {}", e)))
+                .unwrap();
+            None
+        }
+        None => None,
+    };
+    let mut transcript_file = match config.transcript.as_deref().map(TranscriptFile::open) {
+        Some(Ok(file)) => Some(file),
+        Some(Err(e)) => {
+            tx.send(Ui::Error(format!("Could not open the transcript: {}", e)))
+                .unwrap();
+            None
+        }
+        None => None,
+    };
+    let mut stroke_log = if config.stroke_log.unwrap_or(true) {
+        match StrokeLog::open() {
+            Ok(log) => Some(log),
+            Err(e) => {
+                tx.send(Ui::Error(format!("Could not open the stroke log: {}", e)))
+                    .unwrap();
+                None
+            }
+        }
+    } else {
+        None
+    };
+
+    for input in input {
+        let time_start = std::time::Instant::now();
+        let mut engine = engine_lock.lock().unwrap();
+        let stroke = match input {
+            Input::Stroke(stroke) => stroke,
+            Input::Command(command) => {
+                keyboard = run_command(&command, &config, &mut active, &mut engine, &keymap, &tx)
+                    .unwrap_or(keyboard);
+                continue;
             }
+            Input::Focus(window) => {
+                let rule = config.windows.iter().position(|x| x.matches(&window));
+                if rule != active.window {
+                    active.window = rule;
+                    keyboard = switch_settings(&config, &active, &mut engine, &keymap, &tx)
+                        .unwrap_or(keyboard);
+                }
+                continue;
+            }
+        };
+
+        let enabled = engine.is_enabled();
+        let (del, add) = engine.process_stroke(stroke);
+        if engine.is_enabled() != enabled {
+            tx.send(Ui::Output(engine.is_enabled())).unwrap();
         }
+        let outcome = engine.outcome().clone();
+        // Sending can take a while, so the engine is unlocked first
+        drop(engine);
+
+        keyboard.send(&del, &add);
+
         for a in &add {
-            match a {
-                Action::Text(s) => match s.as_str() {
-                    // key_sequence doesn't seem to work for newline, i have to do this stupid
-                    "\n" => enigo.key_click(Key::Return),
-                    _ => enigo.key_sequence(s),
-                },
-                Action::KeyClick(k) => enigo.key_click(*k),
-                Action::KeyUp(k) => enigo.key_up(*k),
-                Action::KeyDown(k) => enigo.key_down(*k),
+            if let Action::Command(command) = a {
+                let mut engine = engine_lock.lock().unwrap();
+                keyboard = run_command(command, &config, &mut active, &mut engine, &keymap, &tx)
+                    .unwrap_or(keyboard);
             }
         }
 
-        tx.send(Ui::Stroke(
+        let event = StrokeEvent {
             stroke,
-            time_start.elapsed().as_millis(),
-            add.len() as i32 - del.len() as i32,
-        ))
-        .unwrap();
+            time: SystemTime::now(),
+            duration: time_start.elapsed().as_millis(),
+            actions: add.len() as i32 - del.len() as i32,
+            outcome,
+            deleted: actions_text(&del),
+            added: actions_text(&add),
+        };
+        {
+            let mut transcript = transcript.lock().unwrap();
+            let kept = transcript.apply(event.time, &del, &add);
+            if let Some(file) = &mut transcript_file {
+                if let Err(e) = file.write(transcript.text(), kept) {
+                    tx.send(Ui::Error(format!(
+                        "Could not write to the transcript: {}",
+                        e
+                    )))
+                    .unwrap();
+                    transcript_file = None;
+                }
+            }
+        }
+        let entry = Entry::new(&event);
+        if let Some(file) = &mut tape {
+            if let Err(e) = file.write(&entry) {
+                tx.send(Ui::Error(format!(
+                    "Could not write to the paper tape: {}",
+                    e
+                )))
+                .unwrap();
+                tape = None;
+            }
+        }
+        if let Some(log) = &mut stroke_log {
+            if let Err(e) = log.write(&entry) {
+                tx.send(Ui::Error(format!(
+                    "Could not write to the stroke log: {}",
+                    e
+                )))
+                .unwrap();
+                stroke_log = None;
+            }
+        }
+        tx.send(Ui::Stroke(event)).unwrap();
     }
 }
 
-fn draw_stroke_display(
-    stdout: &mut std::io::Stdout,
-    dim: (u16, u16),
-    s: &VecDeque<u32>,
-    d: u128,
-    a: f64,
-    e: f64,
-) -> Result<(), std::io::Error> {
-    let w = engine::STENO_ORDER.len() + 2;
-    let x = (dim.0 - w as u16 - 2) / 2;
-    let y = (dim.1 - DISPLAY_LEN) / 2 - 1;
-    queue!(
-        stdout,
-        cursor::MoveTo(x, y),
-        Print(format!("┌{}┐", "─".repeat(w)).dark_grey()),
-    )?;
-    for i in 0..=DISPLAY_LEN {
-        queue!(
-            stdout,
-            cursor::MoveTo(x, y + DISPLAY_LEN + 1 - i as u16),
-            Print("│ ".dark_grey()),
-            Print(
-                engine::id_to_steno(if let Some(x) = s.get(s.len() - i as usize) {
-                    *x
-                } else {
-                    0
-                })
-                .black()
-            ),
-            Print(" │".dark_grey()),
-        )?;
-    }
-    queue!(
-        stdout,
-        cursor::MoveTo(x, y + DISPLAY_LEN + 1),
-        Print(format!("└{}┘", "─".repeat(w)).dark_grey()),
-    )?;
-    let s = format!("SPS: {:.2}    Last: {}ms", a, d);
-    let s2 = format!("CPS: {:.2}", e);
-    let s3 = format!("(last {})", DISPLAY_LEN);
-    queue!(
-        stdout,
-        cursor::MoveTo((dim.0 - s.len() as u16) / 2, (dim.1 + DISPLAY_LEN) / 2 + 2),
-        terminal::Clear(terminal::ClearType::CurrentLine),
-        Print(s),
-        cursor::MoveTo((dim.0 - s2.len() as u16) / 2, (dim.1 + DISPLAY_LEN) / 2 + 3),
-        terminal::Clear(terminal::ClearType::CurrentLine),
-        Print(s2),
-        cursor::MoveTo((dim.0 - s3.len() as u16) / 2, (dim.1 + DISPLAY_LEN) / 2 + 4),
-        terminal::Clear(terminal::ClearType::CurrentLine),
-        Print(s3.dark_grey()),
-    )?;
-    Ok(())
+// The settings the steno loop is using: a profile's, changed by the window
+// rule for the focused window.
+struct Active {
+    profile: String,
+    // Index of the window rule
+    window: Option<usize>,
 }
 
-fn draw_machine_status(
-    stdout: &mut std::io::Stdout,
-    dim: (u16, u16),
-    path: Option<String>,
-) -> Result<(), std::io::Error> {
-    let s = path.unwrap_or(String::from("disconnected"));
-    queue!(
-        stdout,
-        cursor::MoveTo((dim.0 - s.len() as u16) / 2, (dim.1 - DISPLAY_LEN) / 2 - 4),
-        terminal::Clear(terminal::ClearType::CurrentLine),
-        Print(if s.as_str() != "disconnected" {
-            s.green()
-        } else {
-            s.red()
-        })
-    )?;
-    Ok(())
+// Load the active settings, returning the keyboard to use with them.
+fn switch_settings(
+    config: &Config,
+    active: &Active,
+    engine: &mut engine::Engine,
+    keymap: &Option<Arc<Mutex<machine::Keymap>>>,
+    tx: &mpsc::Sender<Ui>,
+) -> Option<keyboard::Keyboard> {
+    let mut settings = match config.with_profile(&active.profile) {
+        Ok(settings) => settings,
+        Err(e) => {
+            tx.send(Ui::Error(e)).unwrap();
+            return None;
+        }
+    };
+    if let Some(i) = active.window {
+        settings = settings.with_window(&config.windows[i]);
+    }
+    tx.send(Ui::Profile(active.profile.clone())).unwrap();
+    match load_settings(engine, &settings, Some(tx)) {
+        Ok(()) => {
+            if let Some(keymap) = keymap {
+                *keymap.lock().unwrap() = settings.keymap().unwrap_or_default();
+            }
+            Some(keyboard::Keyboard::new(settings.paste_length()))
+        }
+        Err(e) => {
+            tx.send(Ui::Error(e)).unwrap();
+            None
+        }
+    }
 }
-fn draw_dict_status(
-    stdout: &mut std::io::Stdout,
-    dim: (u16, u16),
-    n: usize,
-) -> Result<(), std::io::Error> {
-    let s = format!("{} dictionaries loaded", n);
-    queue!(
-        stdout,
-        cursor::MoveTo((dim.0 - s.len() as u16) / 2, (dim.1 - DISPLAY_LEN) / 2 - 3),
-        terminal::Clear(terminal::ClearType::CurrentLine),
-        Print(s),
-    )?;
-    Ok(())
+
+// Carry out an engine command that the engine doesn't handle itself,
+// returning the keyboard to use if the output settings changed.
+fn run_command(
+    command: &str,
+    config: &Config,
+    active: &mut Active,
+    engine: &mut engine::Engine,
+    keymap: &Option<Arc<Mutex<machine::Keymap>>>,
+    tx: &mpsc::Sender<Ui>,
+) -> Option<keyboard::Keyboard> {
+    let (name, arg) = command.split_once(':').unwrap_or((command, ""));
+    match name.to_lowercase().as_str() {
+        // Switch profiles without reconnecting to the machine
+        "profile" => match config.with_profile(arg) {
+            Ok(_) => {
+                active.profile = arg.to_string();
+                return switch_settings(config, active, engine, keymap, tx);
+            }
+            Err(e) => tx.send(Ui::Error(e)).unwrap(),
+        },
+        // Output is turned on and off by the engine itself
+        "toggle" | "on" | "off" | "resume" | "suspend" => {}
+        _ => tx
+            .send(Ui::Error(format!("Unknown command: {}", command)))
+            .unwrap(),
+    }
+    None
 }
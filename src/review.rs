@@ -0,0 +1,299 @@
+/*
+    Spaced repetition of the words that give the writer trouble: words written
+    right after a misstroke was taken back, and words that were fingerspelled.
+    They're found in the stroke log, and reviewed as drills that are scheduled
+    further apart each time they go well. The review state is kept in the data
+    directory.
+*/
+
+use crate::tape::{self, Entry};
+use chrono::{DateTime, Duration, Local, NaiveDate};
+use directories::ProjectDirs;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+// Days of the stroke log looked through for new trouble words
+pub const LOG_DAYS: usize = 30;
+// Most words in a review
+pub const REVIEW_LEN: usize = 20;
+const DATE_FORMAT: &str = "%Y-%m-%d";
+// Only the end of the text is needed to tell which word is being written.
+const TEXT_LEN: usize = 1000;
+// How much longer the interval gets each time a word is reviewed well
+const START_EASE: f64 = 2.5;
+const MIN_EASE: f64 = 1.3;
+
+pub fn path() -> PathBuf {
+    let proj_dirs = ProjectDirs::from("", "", "moreover").unwrap();
+    proj_dirs.data_dir().join("review.txt")
+}
+
+// Words that gave trouble, with when they were written, oldest first.
+pub fn trouble_words(entries: &[Entry]) -> Vec<(DateTime<Local>, String)> {
+    let mut found = vec![];
+    let mut text = String::new();
+    // A misstroke was taken back, so the next word is the one that was meant
+    let mut corrected = false;
+    // Whether the word being written is the one after a misstroke, and how
+    // many of its strokes were single letters
+    let mut misstroked = false;
+    let mut letters = 0;
+    let mut spelled = true;
+
+    for entry in entries {
+        if entry.mark == tape::IGNORED {
+            continue;
+        }
+        let before = text.split_whitespace().count();
+        for _ in entry.deleted.chars() {
+            text.pop();
+        }
+        text.push_str(&entry.added);
+        let words: Vec<&str> = text.split_whitespace().collect();
+        // The stroke started a new word, so the one before is finished
+        if words.len() > before {
+            if before > 0 && (misstroked || (spelled && letters > 1)) {
+                if let Some(word) = word(words[before - 1]) {
+                    found.push((entry.time, word));
+                }
+            }
+            misstroked = corrected && entry.mark == tape::TRANSLATED;
+            corrected = false;
+            letters = 0;
+            spelled = true;
+        }
+        if text.len() > 2 * TEXT_LEN {
+            let cut = (text.len() - TEXT_LEN..text.len())
+                .find(|x| text.is_char_boundary(*x))
+                .unwrap();
+            text.drain(..cut);
+        }
+
+        let added = entry.added.trim_start();
+        if added.chars().count() == 1 && added.chars().all(char::is_alphabetic) {
+            letters += 1;
+        } else if !added.is_empty() {
+            spelled = false;
+        }
+        if entry.mark == tape::UNDO || entry.mark == tape::UNTRANSLATED {
+            corrected = true;
+        }
+    }
+    found
+}
+
+// The word without punctuation around it, if it's a word.
+fn word(s: &str) -> Option<String> {
+    let s = s.trim_matches(|c: char| !c.is_alphanumeric());
+    let is_word = !s.is_empty() && s.chars().all(|c| c.is_alphabetic() || c == '\'');
+    is_word.then(|| s.to_string())
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Card {
+    due: NaiveDate,
+    // Days until the next review after a good one
+    interval: i64,
+    ease: f64,
+    // Times the word gave trouble
+    lapses: u32,
+}
+impl Card {
+    fn new(today: NaiveDate) -> Self {
+        Self {
+            due: today,
+            interval: 1,
+            ease: START_EASE,
+            lapses: 1,
+        }
+    }
+}
+
+pub struct Deck {
+    path: PathBuf,
+    // Log entries up to this time have been looked through
+    scanned: Option<DateTime<Local>>,
+    cards: BTreeMap<String, Card>,
+}
+impl Deck {
+    // Read the review state, which is empty if there isn't any yet. Lines
+    // that can't be read are skipped.
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let mut deck = Self {
+            path,
+            scanned: None,
+            cards: BTreeMap::new(),
+        };
+        let text = match fs::read_to_string(&deck.path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(deck),
+            Err(e) => return Err(e),
+        };
+        let mut lines = text.lines();
+        deck.scanned = lines
+            .next()
+            .and_then(|x| x.strip_prefix("scanned\t"))
+            .and_then(|x| DateTime::parse_from_rfc3339(x).ok())
+            .map(|x| x.into());
+        for line in lines {
+            let parts: Vec<&str> = line.split('\t').collect();
+            let card = match parts[..] {
+                [word, due, interval, ease, lapses] => (|| {
+                    let card = Card {
+                        due: NaiveDate::parse_from_str(due, DATE_FORMAT).ok()?,
+                        interval: interval.parse().ok()?,
+                        ease: ease.parse().ok()?,
+                        lapses: lapses.parse().ok()?,
+                    };
+                    Some((word.to_string(), card))
+                })(),
+                _ => None,
+            };
+            if let Some((word, card)) = card {
+                deck.cards.insert(word, card);
+            }
+        }
+        Ok(deck)
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        let mut text = match self.scanned {
+            Some(time) => format!("scanned\t{}\n", time.to_rfc3339()),
+            None => "scanned\t\n".to_string(),
+        };
+        for (word, card) in &self.cards {
+            text.push_str(&format!(
+                "{}\t{}\t{}\t{}\t{}\n",
+                word,
+                card.due.format(DATE_FORMAT),
+                card.interval,
+                card.ease,
+                card.lapses
+            ));
+        }
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        // Written next to the file first, so that it's never left half written
+        let temp = self.path.with_extension("tmp");
+        fs::write(&temp, text)?;
+        fs::rename(temp, &self.path)
+    }
+
+    // Add the trouble words in log entries that haven't been looked through.
+    // Words that are already being reviewed start over, due today.
+    pub fn scan(&mut self, entries: &[Entry]) {
+        for (time, word) in trouble_words(entries) {
+            if self.scanned.is_some_and(|x| time <= x) {
+                continue;
+            }
+            let today = time.date_naive();
+            self.cards
+                .entry(word)
+                .and_modify(|x| x.lapse(today))
+                .or_insert_with(|| Card::new(today));
+        }
+        if let Some(last) = entries.last() {
+            self.scanned = self.scanned.max(Some(last.time));
+        }
+    }
+
+    // Words due for review, the most overdue and troublesome first.
+    pub fn due(&self, today: NaiveDate, n: usize) -> Vec<String> {
+        let mut due: Vec<(&String, &Card)> =
+            self.cards.iter().filter(|x| x.1.due <= today).collect();
+        due.sort_by(|a, b| a.1.due.cmp(&b.1.due).then(b.1.lapses.cmp(&a.1.lapses)));
+        due.into_iter().take(n).map(|x| x.0.clone()).collect()
+    }
+
+    // Schedule a word after reviewing it.
+    pub fn review(&mut self, word: &str, good: bool, today: NaiveDate) {
+        let Some(card) = self.cards.get_mut(word) else {
+            return;
+        };
+        if good {
+            card.due = today + Duration::days(card.interval);
+            card.interval = ((card.interval as f64) * card.ease).round() as i64;
+            card.ease += 0.1;
+        } else {
+            card.lapse(today);
+            card.due = today + Duration::days(1);
+        }
+    }
+}
+impl Card {
+    fn lapse(&mut self, today: NaiveDate) {
+        self.due = today;
+        self.interval = 1;
+        self.ease = (self.ease - 0.2).max(MIN_EASE);
+        self.lapses += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use moreover::engine;
+
+    fn entry(time: i64, mark: char, deleted: &str, added: &str) -> Entry {
+        Entry {
+            time: DateTime::from_timestamp(time, 0).unwrap().into(),
+            stroke: engine::steno_to_id("S"),
+            mark,
+            translation: String::new(),
+            source: String::new(),
+            deleted: deleted.to_string(),
+            added: added.to_string(),
+        }
+    }
+
+    #[test]
+    fn review() {
+        let entries = [
+            entry(0, tape::TRANSLATED, "", " the"),
+            entry(1, tape::TRANSLATED, "", " cart"),
+            entry(2, tape::UNDO, " cart", ""),
+            entry(3, tape::TRANSLATED, "", " cat"),
+            entry(4, tape::TRANSLATED, " cat", " cats"),
+            entry(5, tape::TRANSLATED, "", " s"),
+            entry(6, tape::TRANSLATED, "", "a"),
+            entry(7, tape::TRANSLATED, "", "t"),
+            entry(8, tape::TRANSLATED, "", ". The"),
+            entry(9, tape::UNTRANSLATED, "", " STK"),
+            entry(10, tape::TRANSLATED, "", " end"),
+        ];
+        let words: Vec<String> = trouble_words(&entries).into_iter().map(|x| x.1).collect();
+        // The word after the undo with its suffix, and the fingerspelled word.
+        // The untranslated stroke was left, so the word after it isn't finished.
+        assert_eq!(words, vec!["cats", "sat"]);
+
+        let dir = std::env::temp_dir().join(format!("moreover-review-{}", std::process::id()));
+        let path = dir.join("review.txt");
+        let mut deck = Deck::load(&path).unwrap();
+        deck.scan(&entries);
+        let today = entries[0].time.date_naive();
+        assert_eq!(deck.due(today, 10), vec!["cats", "sat"]);
+
+        deck.review("cats", true, today);
+        deck.review("sat", false, today);
+        assert!(deck.due(today, 10).is_empty());
+        let tomorrow = today + Duration::days(1);
+        // The word that gave more trouble first
+        assert_eq!(deck.due(tomorrow, 10), vec!["sat", "cats"]);
+        deck.review("cats", true, tomorrow);
+        // The interval grows
+        assert_eq!(deck.cards["cats"].due, tomorrow + Duration::days(3));
+        assert_eq!(deck.cards["sat"].lapses, 2);
+
+        // Entries that were already looked through aren't counted again
+        deck.save().unwrap();
+        let mut saved = Deck::load(&path).unwrap();
+        assert_eq!(saved.cards, deck.cards);
+        saved.scan(&entries);
+        assert_eq!(saved.cards, deck.cards);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
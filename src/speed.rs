@@ -0,0 +1,212 @@
+/*
+    Writing speed from the text that strokes type, in actual words and in
+    standard five character words, over rolling windows and the whole session.
+*/
+
+use std::collections::VecDeque;
+use std::time::{Duration, SystemTime};
+
+// How long samples are kept for, which is the longest window that can be asked for.
+const HISTORY: Duration = Duration::from_secs(300);
+// Longer pauses between strokes don't count towards the session's time.
+const PAUSE: Duration = Duration::from_secs(5);
+pub const PEAK_WINDOW: Duration = Duration::from_secs(10);
+// Only the end of the text is needed to tell where words start.
+const TEXT_LEN: usize = 1000;
+
+// Words per minute.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Rate {
+    pub words: f64,
+    // Characters divided by five
+    pub standard: f64,
+}
+impl Rate {
+    fn new(words: i64, chars: i64, time: Duration) -> Self {
+        let minutes = time.as_secs_f64() / 60.0;
+        if minutes == 0.0 {
+            return Self::default();
+        }
+        Self {
+            words: words as f64 / minutes,
+            standard: chars as f64 / 5.0 / minutes,
+        }
+    }
+}
+
+struct Sample {
+    time: SystemTime,
+    words: i64,
+    chars: i64,
+}
+
+#[derive(Default)]
+pub struct Speed {
+    samples: VecDeque<Sample>,
+    text: String,
+    // When the current run of writing started
+    start: Option<SystemTime>,
+    last: Option<SystemTime>,
+    active: Duration,
+    words: i64,
+    chars: i64,
+    peak: f64,
+}
+impl Speed {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Add the text a stroke took back and typed.
+    pub fn add(&mut self, time: SystemTime, deleted: &str, added: &str) {
+        for _ in deleted.chars() {
+            self.text.pop();
+        }
+        let prev = self.text.chars().last();
+        let words = word_starts(prev, added) - word_starts(prev, deleted);
+        let chars = added.chars().count() as i64 - deleted.chars().count() as i64;
+        self.text.push_str(added);
+        trim(&mut self.text);
+
+        let gap = self
+            .last
+            .and_then(|x| time.duration_since(x).ok())
+            .unwrap_or_default();
+        if self.last.is_none() || gap > PAUSE {
+            self.start = Some(time);
+        }
+        self.active += gap.min(PAUSE);
+        self.last = Some(time);
+        self.words += words;
+        self.chars += chars;
+
+        self.samples.push_back(Sample { time, words, chars });
+        while let Some(s) = self.samples.front() {
+            match time.duration_since(s.time) {
+                Ok(d) if d > HISTORY => self.samples.pop_front(),
+                _ => break,
+            };
+        }
+
+        if self.writing_time(time) >= PEAK_WINDOW {
+            self.peak = self.peak.max(self.window(time, PEAK_WINDOW).words);
+        }
+    }
+
+    // Time spent writing without a long pause, up to now.
+    fn writing_time(&self, now: SystemTime) -> Duration {
+        self.start
+            .and_then(|x| now.duration_since(x).ok())
+            .unwrap_or_default()
+    }
+
+    // Speed over the window ending now. Until there's been a window's worth
+    // of writing, it's over the time written so far.
+    pub fn window(&self, now: SystemTime, window: Duration) -> Rate {
+        let (mut words, mut chars) = (0, 0);
+        for s in self.samples.iter().rev() {
+            match now.duration_since(s.time) {
+                Ok(d) if d > window => break,
+                _ => {
+                    words += s.words;
+                    chars += s.chars;
+                }
+            }
+        }
+        let time = window.min(self.writing_time(now).max(Duration::from_secs(1)));
+        Rate::new(words, chars, time)
+    }
+
+    // Speed over the session, leaving out long pauses.
+    pub fn session(&self) -> Rate {
+        Rate::new(self.words, self.chars, self.active)
+    }
+
+    // Fastest words per minute over PEAK_WINDOW.
+    pub fn peak(&self) -> f64 {
+        self.peak
+    }
+
+    // Words per minute in each of the last n periods, oldest first.
+    pub fn history(&self, now: SystemTime, period: Duration, n: usize) -> Vec<f64> {
+        let mut words = vec![0; n];
+        for s in &self.samples {
+            let age = now.duration_since(s.time).unwrap_or_default();
+            let i = (age.as_secs_f64() / period.as_secs_f64()) as usize;
+            if i < n {
+                words[n - 1 - i] += s.words;
+            }
+        }
+        words
+            .into_iter()
+            .map(|x| Rate::new(x, 0, period).words.max(0.0))
+            .collect()
+    }
+}
+
+// Drop the start of text that's grown well past TEXT_LEN.
+pub fn trim(text: &mut String) {
+    if text.len() > 2 * TEXT_LEN {
+        let cut = text.len() - TEXT_LEN;
+        let cut = (cut..text.len())
+            .find(|x| text.is_char_boundary(*x))
+            .unwrap();
+        text.drain(..cut);
+    }
+}
+
+// Number of words that start in the text, given the character before it.
+pub fn word_starts(mut prev: Option<char>, s: &str) -> i64 {
+    let mut n = 0;
+    for c in s.chars() {
+        if !c.is_whitespace() && prev.is_none_or(|x| x.is_whitespace()) {
+            n += 1;
+        }
+        prev = Some(c);
+    }
+    n
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn speed() {
+        let start = SystemTime::UNIX_EPOCH;
+        let at = |ms| start + Duration::from_millis(ms);
+        let mut speed = Speed::new();
+        speed.add(at(0), "", " the");
+        speed.add(at(1000), "", " cat");
+        // Suffixes, punctuation and undos don't add words
+        speed.add(at(2000), " cat", " cats");
+        speed.add(at(3000), "", " sat");
+        speed.add(at(4000), " sat", "");
+        speed.add(at(5000), "", ",");
+        speed.add(at(6000), ",", "");
+        speed.add(at(10000), "", " on");
+
+        // 3 words and 12 characters in 10 seconds
+        let rate = speed.window(at(10000), Duration::from_secs(60));
+        assert_eq!(rate, Rate::new(3, 12, Duration::from_secs(10)));
+        assert_eq!(rate.words, 18.0);
+        assert_eq!(speed.session(), rate);
+        assert_eq!(speed.peak(), 18.0);
+        // Only " on" is in the last 3 seconds
+        assert_eq!(
+            speed.window(at(10000), Duration::from_secs(3)),
+            Rate::new(1, 3, Duration::from_secs(3))
+        );
+        assert_eq!(
+            speed.history(at(10000), Duration::from_secs(5), 3),
+            vec![12.0, 12.0, 12.0]
+        );
+
+        // A long pause starts a new run of writing
+        speed.add(at(100000), "", " it");
+        assert_eq!(
+            speed.window(at(100000), Duration::from_secs(60)).words,
+            60.0
+        );
+    }
+}
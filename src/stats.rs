@@ -0,0 +1,157 @@
+/*
+    Writing statistics, collected from stroke log entries.
+*/
+
+use crate::speed;
+use crate::tape::{self, Entry};
+use chrono::{DateTime, Local};
+use moreover::engine;
+use std::collections::HashMap;
+
+// Longer pauses between strokes don't count as time spent writing.
+const PAUSE_MS: i64 = 5000;
+
+#[derive(Default)]
+pub struct Stats {
+    strokes: usize,
+    undos: usize,
+    // Writing time in milliseconds
+    active: i64,
+    last: Option<DateTime<Local>>,
+    // The end of the text written, to tell where words start
+    text: String,
+    words: i64,
+    // Strokes that an undo would take back, and whether they were untranslated
+    undoable: Vec<(u32, bool)>,
+    // Outlines that were untranslated or undone, by how often
+    misstrokes: HashMap<u32, usize>,
+}
+impl Stats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&mut self, entry: &Entry) {
+        // Strokes while output is off aren't writing
+        if entry.mark == tape::IGNORED {
+            return;
+        }
+        if let Some(last) = self.last {
+            let gap = (entry.time - last).num_milliseconds();
+            self.active += gap.clamp(0, PAUSE_MS);
+        }
+        self.last = Some(entry.time);
+        self.strokes += 1;
+
+        for _ in entry.deleted.chars() {
+            self.text.pop();
+        }
+        let prev = self.text.chars().last();
+        self.words +=
+            speed::word_starts(prev, &entry.added) - speed::word_starts(prev, &entry.deleted);
+        self.text.push_str(&entry.added);
+        speed::trim(&mut self.text);
+
+        match entry.mark {
+            tape::UNDO => {
+                self.undos += 1;
+                // Untranslated strokes were already counted
+                if let Some((stroke, false)) = self.undoable.pop() {
+                    *self.misstrokes.entry(stroke).or_default() += 1;
+                }
+            }
+            tape::UNTRANSLATED => {
+                *self.misstrokes.entry(entry.stroke).or_default() += 1;
+                self.undoable.push((entry.stroke, true));
+            }
+            _ => self.undoable.push((entry.stroke, false)),
+        }
+    }
+
+    pub fn strokes(&self) -> usize {
+        self.strokes
+    }
+
+    pub fn words(&self) -> usize {
+        self.words.max(0) as usize
+    }
+
+    pub fn wpm(&self) -> f64 {
+        if self.active == 0 {
+            return 0.0;
+        }
+        self.words() as f64 / (self.active as f64 / 60000.0)
+    }
+
+    pub fn strokes_per_word(&self) -> f64 {
+        if self.words() == 0 {
+            return 0.0;
+        }
+        self.strokes as f64 / self.words() as f64
+    }
+
+    // Fraction of strokes that were undos.
+    pub fn undo_rate(&self) -> f64 {
+        if self.strokes == 0 {
+            return 0.0;
+        }
+        self.undos as f64 / self.strokes as f64
+    }
+
+    // The outlines misstroked most often, with how often.
+    pub fn misstrokes(&self, n: usize) -> Vec<(String, usize)> {
+        let mut misstrokes: Vec<(String, usize)> = self
+            .misstrokes
+            .iter()
+            .map(|(stroke, count)| (engine::id_to_compact_steno(*stroke), *count))
+            .collect();
+        misstrokes.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        misstrokes.truncate(n);
+        misstrokes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(time: i64, stroke: &str, mark: char, deleted: &str, added: &str) -> Entry {
+        Entry {
+            time: DateTime::from_timestamp_millis(time).unwrap().into(),
+            stroke: engine::steno_to_id(stroke),
+            mark,
+            translation: String::new(),
+            source: String::new(),
+            deleted: deleted.to_string(),
+            added: added.to_string(),
+        }
+    }
+
+    #[test]
+    fn stats() {
+        let mut stats = Stats::new();
+        for e in [
+            entry(0, "Tt", tape::TRANSLATED, "", " the"),
+            entry(1000, "KAt", tape::TRANSLATED, "", " cat"),
+            entry(2000, "s", tape::TRANSLATED, " cat", " cats"),
+            entry(3000, "STK", tape::UNTRANSLATED, "", " STK"),
+            entry(4000, "*", tape::UNDO, " STK", ""),
+            entry(5000, "*", tape::UNDO, " cats", " cat"),
+            // Long pauses and strokes while output is off don't count
+            entry(60000, "Tt", tape::TRANSLATED, "", " the"),
+            entry(61000, "KAt", tape::IGNORED, "", ""),
+        ] {
+            stats.add(&e);
+        }
+        assert_eq!(stats.strokes(), 7);
+        assert_eq!(stats.words(), 3);
+        // 3 words in 10 seconds
+        assert!((stats.wpm() - 18.0).abs() < 1e-9);
+        assert!((stats.strokes_per_word() - 7.0 / 3.0).abs() < 1e-9);
+        assert!((stats.undo_rate() - 2.0 / 7.0).abs() < 1e-9);
+        assert_eq!(
+            stats.misstrokes(5),
+            vec![("STK".to_string(), 1), ("s".to_string(), 1)]
+        );
+    }
+}
@@ -0,0 +1,156 @@
+/*
+    Paper tape of strokes and what they did, shown in the display and
+    optionally written to a file. The stroke log uses the same format.
+*/
+
+use chrono::{DateTime, Local, NaiveDateTime, TimeZone};
+use moreover::{engine, Outcome, StrokeEvent};
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+
+const TIME_FORMAT: &str = "%Y-%m-%d %H:%M:%S%.3f";
+
+pub const TRANSLATED: char = ' ';
+pub const UNTRANSLATED: char = '?';
+pub const UNDO: char = '<';
+// Output was off
+pub const IGNORED: char = '-';
+
+// Mark shown next to a stroke, and the translation it produced.
+pub fn describe(outcome: &Outcome) -> (char, String) {
+    match outcome {
+        Outcome::Translated(translations) => {
+            let raw: Vec<&str> = translations.iter().map(|x| x.raw()).collect();
+            (TRANSLATED, raw.join(" "))
+        }
+        Outcome::Untranslated => (UNTRANSLATED, String::new()),
+        Outcome::Undo => (UNDO, String::new()),
+        Outcome::Ignored => (IGNORED, String::new()),
+    }
+}
+
+// Name of the dictionary the stroke's translation came from, if any.
+pub fn source(outcome: &Outcome) -> &str {
+    match outcome {
+        Outcome::Translated(translations) => translations[0].source().unwrap_or_default(),
+        _ => "",
+    }
+}
+
+// A line of the tape.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    pub time: DateTime<Local>,
+    pub stroke: u32,
+    pub mark: char,
+    pub translation: String,
+    pub source: String,
+    // Text taken back and typed by the stroke
+    pub deleted: String,
+    pub added: String,
+}
+impl Entry {
+    pub fn new(event: &StrokeEvent) -> Self {
+        let (mark, translation) = describe(&event.outcome);
+        Self {
+            time: event.time.into(),
+            stroke: event.stroke,
+            mark,
+            translation,
+            source: source(&event.outcome).to_string(),
+            deleted: event.deleted.clone(),
+            added: event.added.clone(),
+        }
+    }
+
+    pub fn parse(line: &str) -> Option<Self> {
+        let mut parts = line.split('\t');
+        let time = NaiveDateTime::parse_from_str(parts.next()?, TIME_FORMAT).ok()?;
+        Some(Self {
+            time: Local.from_local_datetime(&time).earliest()?,
+            stroke: engine::parse_steno(&parts.next()?.replace(' ', ""))?,
+            mark: parts.next()?.chars().next()?,
+            translation: parts.next()?.to_string(),
+            source: parts.next()?.to_string(),
+            deleted: unescape(parts.next()?),
+            added: unescape(parts.next()?),
+        })
+    }
+}
+// Time, stroke, mark, translation, dictionary, and the text changes,
+// separated by tabs.
+impl fmt::Display for Entry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}\t{}\t{}\t{}\t{}\t{}\t{}",
+            self.time.format(TIME_FORMAT),
+            engine::id_to_steno(self.stroke),
+            self.mark,
+            self.translation,
+            self.source,
+            escape(&self.deleted),
+            escape(&self.added),
+        )
+    }
+}
+
+// Typed text can hold the characters used to separate entries.
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace('\t', "\\t")
+        .replace('\n', "\\n")
+}
+fn unescape(s: &str) -> String {
+    let mut unescaped = String::new();
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('t') => unescaped.push('\t'),
+            Some('n') => unescaped.push('\n'),
+            Some(c) => unescaped.push(c),
+            None => {}
+        }
+    }
+    unescaped
+}
+
+pub struct TapeFile {
+    file: File,
+}
+impl TapeFile {
+    // Open a tape file, adding to the end of it if it exists.
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self { file })
+    }
+
+    pub fn write(&mut self, entry: &Entry) -> io::Result<()> {
+        writeln!(self.file, "{}", entry)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let entry = Entry {
+            time: Local.with_ymd_and_hms(2022, 3, 4, 5, 6, 7).unwrap(),
+            stroke: engine::steno_to_id("KAt"),
+            mark: TRANSLATED,
+            translation: "{^\\n^}cat".to_string(),
+            source: "main.txt".to_string(),
+            deleted: "a\\b".to_string(),
+            added: "\n\tcat".to_string(),
+        };
+        assert_eq!(Entry::parse(&entry.to_string()), Some(entry));
+    }
+}
@@ -0,0 +1,52 @@
+/*
+    Transcript file, kept up to date with the engine's transcript as strokes
+    correct it. Each run's transcript is added after what's already in the file.
+*/
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, Seek, SeekFrom, Write};
+use std::path::Path;
+
+pub struct TranscriptFile {
+    file: File,
+    // Length of the file before this run
+    start: u64,
+}
+impl TranscriptFile {
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(path)?;
+        let start = file.metadata()?.len();
+        Ok(Self { file, start })
+    }
+
+    // Rewrite the file from where the text changed, which is the length of
+    // the text that was kept.
+    pub fn write(&mut self, text: &str, kept: usize) -> io::Result<()> {
+        let offset = self.start + kept as u64;
+        self.file.set_len(offset)?;
+        self.file.seek(SeekFrom::Start(offset))?;
+        self.file.write_all(&text.as_bytes()[kept..])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn write() {
+        let path = std::env::temp_dir().join(format!("moreover-transcript-{}", std::process::id()));
+        fs::write(&path, "Before.\n").unwrap();
+        let mut file = TranscriptFile::open(&path).unwrap();
+        file.write("The cat", 0).unwrap();
+        file.write("The cats sat", 4).unwrap();
+        file.write("The cats", 8).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "Before.\nThe cats");
+        fs::remove_file(path).unwrap();
+    }
+}
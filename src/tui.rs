@@ -0,0 +1,1004 @@
+/*
+    Terminal display of the engine's state, with screens for looking up and
+    changing dictionary entries, turning dictionaries on and off and changing
+    their priorities, and for practice drills.
+*/
+
+use crate::config::{self, DEFAULT_PROFILE};
+use crate::diagram;
+use crate::drill::{self, Drill};
+use crate::history;
+use crate::lookup;
+use crate::review::{self, Deck};
+use crate::speed::Speed;
+use crate::stats::Stats;
+use crate::tape;
+use crate::Ui;
+use chrono::Local;
+use crossterm::event::{Event, KeyCode, KeyEvent, KeyModifiers};
+use crossterm::style::{Print, Stylize};
+use crossterm::{cursor, event, execute, queue, terminal};
+use moreover::{engine, Engine, StrokeEvent};
+use std::fs;
+use std::io::{stdout, Stdout, Write};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use std::{collections::VecDeque, sync::mpsc};
+
+const DISPLAY_LEN: u16 = 15;
+// The keyboard diagram's rows, and a blank line under it
+const DIAGRAM_HEIGHT: u16 = 6;
+const TICK_RATE: Duration = Duration::from_secs(1);
+// The speed graph shows the last five minutes
+const GRAPH_PERIOD: Duration = Duration::from_secs(10);
+const GRAPH_LEN: usize = 30;
+const TRANSLATION_LEN: usize = 20;
+const SOURCE_LEN: usize = 14;
+// Steno, mark, translation and source, with spaces in between
+const TAPE_WIDTH: usize = engine::STENO_ORDER.len() + 3 + TRANSLATION_LEN + 1 + SOURCE_LEN;
+// Outline or dictionary, and translation, with the state of the entry
+const LOOKUP_WIDTH: usize = 4 + SOURCE_LEN + 1 + 2 * TRANSLATION_LEN + 1 + 10;
+
+// A line of lookup results: an outline or dictionary, the translation, a note
+// about the entry, and whether it's the one that's used.
+type Row = (String, String, String, bool);
+
+// What the display is showing.
+enum Mode {
+    Normal,
+    // The outlines for a word being typed
+    Words(String),
+    // The translations of an outline being typed. A dictionary is selected
+    // for editing, with the new translation while it's being typed.
+    Strokes {
+        input: String,
+        selected: usize,
+        edit: Option<String>,
+    },
+    // A drill, with the review state if it's reviewing trouble words
+    Drill(Drill, Option<Deck>),
+    // The dictionaries by priority, with one selected
+    Dictionaries(usize),
+}
+
+// Draw messages from the engine until the display is quit with Ctrl+C.
+pub fn run(
+    tx: mpsc::Sender<Ui>,
+    rx: mpsc::Receiver<Ui>,
+    engine: Arc<Mutex<Engine>>,
+    drill: config::Drill,
+) -> Result<(), std::io::Error> {
+    std::thread::spawn(move || {
+        event_loop(tx).unwrap();
+    });
+
+    // Keys are read as they're pressed, and Ctrl+C doesn't stop the process
+    terminal::enable_raw_mode()?;
+    let result = Display::new(engine, drill).and_then(|x| x.run(rx));
+    terminal::disable_raw_mode()?;
+    execute!(
+        stdout(),
+        terminal::Clear(terminal::ClearType::All),
+        cursor::MoveTo(0, 0)
+    )?;
+    result
+}
+
+fn event_loop(tx: mpsc::Sender<Ui>) -> crossterm::Result<()> {
+    loop {
+        if !event::poll(TICK_RATE)? {
+            tx.send(Ui::Tick).unwrap();
+            continue;
+        }
+        match event::read()? {
+            Event::Resize(w, h) => tx.send(Ui::Resize(w, h)).unwrap(),
+            Event::Key(key) => tx.send(Ui::Key(key)).unwrap(),
+            _ => {}
+        }
+    }
+}
+
+struct Display {
+    stdout: Stdout,
+    dim: (u16, u16),
+    engine: Arc<Mutex<Engine>>,
+    drill: config::Drill,
+    mode: Mode,
+    // Result of the last change to a dictionary
+    message: Result<String, String>,
+    display_buffer: VecDeque<StrokeEvent>,
+    dicts: usize,
+    machine_status: String,
+    errors: Vec<String>,
+    profile: String,
+    output: bool,
+    session: Stats,
+    speed: Speed,
+    // Words for drills from the dictionaries, and the dictionaries that were on
+    // when they were listed
+    drill_words: Option<(Vec<usize>, Vec<String>)>,
+    // The last word hinted at in a drill, and its hint
+    hint: Option<(String, String)>,
+}
+impl Display {
+    fn new(engine: Arc<Mutex<Engine>>, drill: config::Drill) -> Result<Self, std::io::Error> {
+        Ok(Self {
+            stdout: stdout(),
+            dim: terminal::size()?,
+            engine,
+            drill,
+            mode: Mode::Normal,
+            message: Ok(String::new()),
+            display_buffer: VecDeque::new(),
+            dicts: 0,
+            machine_status: String::new(),
+            errors: vec![],
+            profile: String::from(DEFAULT_PROFILE),
+            output: true,
+            session: Stats::new(),
+            speed: Speed::new(),
+            drill_words: None,
+            hint: None,
+        })
+    }
+
+    fn run(mut self, rx: mpsc::Receiver<Ui>) -> Result<(), std::io::Error> {
+        self.draw()?;
+        while let Ok(msg) = rx.recv() {
+            // The state is kept up to date while looking things up, and drawn
+            // when going back to the normal display
+            let normal = matches!(self.mode, Mode::Normal);
+            let stdout = &mut self.stdout;
+            let dim = self.dim;
+            match msg {
+                Ui::Stroke(s) => {
+                    self.session.add(&tape::Entry::new(&s));
+                    self.speed.add(s.time, &s.deleted, &s.added);
+                    self.display_buffer.push_back(s);
+                    if self.display_buffer.len() > DISPLAY_LEN.into() {
+                        self.display_buffer.pop_front();
+                    }
+                    if normal {
+                        draw_stroke_display(stdout, dim, &self.display_buffer)?;
+                        draw_speed(stdout, dim, &self.speed)?;
+                        draw_session_stats(stdout, dim, &self.session)?;
+                    }
+                    if let Mode::Drill(d, review) = &mut self.mode {
+                        let s = self.display_buffer.back().unwrap();
+                        let done = d.is_done();
+                        d.add(s.time, &s.deleted, &s.added);
+                        if let Some(deck) = review.as_mut().filter(|_| !done && d.is_done()) {
+                            self.message = finish_review(d, deck);
+                        }
+                        self.draw_drill()?;
+                    }
+                }
+                Ui::Machine(s) => {
+                    self.machine_status = s;
+                    if normal {
+                        draw_machine_status(stdout, dim, &self.machine_status)?;
+                    }
+                }
+                Ui::DictionaryLoaded => {
+                    self.dicts += 1;
+                    self.drill_words = None;
+                    self.hint = None;
+                    if normal {
+                        draw_dict_status(stdout, dim, self.dicts)?;
+                    }
+                }
+                Ui::Profile(p) => {
+                    self.profile = p;
+                    self.dicts = 0;
+                    if normal {
+                        draw_profile(stdout, dim, &self.profile)?;
+                        draw_dict_status(stdout, dim, self.dicts)?;
+                    }
+                }
+                Ui::Output(on) => {
+                    self.output = on;
+                    if normal {
+                        draw_output_status(stdout, dim, self.output)?;
+                    }
+                }
+                Ui::Error(e) => {
+                    self.errors.push(e);
+                    if normal {
+                        draw_errors(stdout, dim, &self.errors)?;
+                    }
+                }
+                // Rolling speeds go down while nothing is written
+                Ui::Tick if normal => draw_speed(stdout, dim, &self.speed)?,
+                // Hints are shown after a while without a stroke
+                Ui::Tick if matches!(self.mode, Mode::Drill(..)) => self.draw_drill()?,
+                Ui::Tick => {}
+                Ui::Resize(w, h) => {
+                    self.dim = (w, h);
+                    self.draw()?;
+                }
+                Ui::Key(key) => {
+                    if key.code == KeyCode::Char('c')
+                        && key.modifiers.contains(KeyModifiers::CONTROL)
+                    {
+                        return Ok(());
+                    }
+                    self.key(key);
+                    self.draw()?;
+                }
+            }
+            self.stdout.flush()?;
+        }
+        Ok(())
+    }
+
+    // Everything for the current mode.
+    fn draw(&mut self) -> Result<(), std::io::Error> {
+        let stdout = &mut self.stdout;
+        let dim = self.dim;
+        execute!(stdout, terminal::Clear(terminal::ClearType::All))?;
+        match &self.mode {
+            Mode::Normal => {
+                draw_output_status(stdout, dim, self.output)?;
+                draw_profile(stdout, dim, &self.profile)?;
+                draw_dict_status(stdout, dim, self.dicts)?;
+                draw_machine_status(stdout, dim, &self.machine_status)?;
+                draw_stroke_display(stdout, dim, &self.display_buffer)?;
+                draw_speed(stdout, dim, &self.speed)?;
+                draw_session_stats(stdout, dim, &self.session)?;
+                draw_errors(stdout, dim, &self.errors)?;
+                draw_keys(
+                    stdout,
+                    dim,
+                    "l: look up a word   s: look up strokes   p: dictionaries   d: drill   r: review   Ctrl+C: quit",
+                )?;
+            }
+            Mode::Words(input) => {
+                let engine = self.engine.lock().unwrap();
+                let rows: Vec<Row> = lookup::outlines(&engine, input)
+                    .into_iter()
+                    .map(|(outline, name)| (outline, name, String::new(), true))
+                    .collect();
+                draw_lookup(stdout, dim, "Look up a word", Some(input), &rows, None)?;
+                draw_keys(stdout, dim, "Esc: back")?;
+            }
+            Mode::Strokes {
+                input,
+                selected,
+                edit,
+            } => {
+                let engine = self.engine.lock().unwrap();
+                let rows: Vec<Row> = match engine::parse_outline(input) {
+                    Some(outline) if !outline.is_empty() => lookup::translations(&engine, &outline)
+                        .into_iter()
+                        .map(|x| {
+                            let note = match (&x.translation, x.used, x.writable) {
+                                _ if !x.enabled => "off",
+                                (Some(_), false, _) => "overridden",
+                                (_, _, false) => "read-only",
+                                _ => "",
+                            };
+                            let translation = x.translation.unwrap_or_else(|| "-".into());
+                            (x.name, translation, note.to_string(), x.used)
+                        })
+                        .collect(),
+                    _ => vec![],
+                };
+                let selected = Some((*selected, edit.as_deref()));
+                draw_lookup(stdout, dim, "Look up strokes", Some(input), &rows, selected)?;
+                draw_message(stdout, dim, &self.message)?;
+                draw_keys(
+                    stdout,
+                    dim,
+                    "Up/Down: select   Enter: edit   Delete: remove   Esc: back",
+                )?;
+            }
+            Mode::Drill(..) => draw_keys(stdout, dim, "Esc: back")?,
+            Mode::Dictionaries(selected) => {
+                let engine = self.engine.lock().unwrap();
+                let rows: Vec<Row> = engine
+                    .dictionaries()
+                    .map(|x| {
+                        let note = match (x.is_enabled(), x.is_writable()) {
+                            (false, _) => "off",
+                            (true, false) => "read-only",
+                            _ => "",
+                        };
+                        let id = format!("id {}", x.id());
+                        (x.name().to_string(), id, note.to_string(), x.is_enabled())
+                    })
+                    .collect();
+                let selected = Some((*selected, None));
+                draw_lookup(stdout, dim, "Dictionaries", None, &rows, selected)?;
+                draw_keys(
+                    stdout,
+                    dim,
+                    "Up/Down: select   Enter: turn on or off   +/-: raise or lower   Esc: back",
+                )?;
+            }
+        }
+        self.draw_drill()
+    }
+
+    fn key(&mut self, key: KeyEvent) {
+        match &mut self.mode {
+            Mode::Normal => match key.code {
+                KeyCode::Char('l') => self.mode = Mode::Words(String::new()),
+                KeyCode::Char('d') => self.start_drill(),
+                KeyCode::Char('r') => self.start_review(),
+                KeyCode::Char('p') => self.mode = Mode::Dictionaries(0),
+                KeyCode::Char('s') => {
+                    self.message = Ok(String::new());
+                    self.mode = Mode::Strokes {
+                        input: String::new(),
+                        selected: 0,
+                        edit: None,
+                    };
+                }
+                _ => {}
+            },
+            Mode::Words(input) => {
+                if key.code == KeyCode::Esc {
+                    self.mode = Mode::Normal;
+                } else {
+                    edit_text(input, key);
+                }
+            }
+            Mode::Strokes {
+                input,
+                selected,
+                edit: edit @ None,
+            } => match key.code {
+                KeyCode::Esc => self.mode = Mode::Normal,
+                KeyCode::Up => *selected = selected.saturating_sub(1),
+                KeyCode::Down => {
+                    let n = self.engine.lock().unwrap().dictionaries().count();
+                    *selected = (*selected + 1).min(n - 1);
+                }
+                KeyCode::Enter => {
+                    let outline = engine::parse_outline(input).filter(|x| !x.is_empty());
+                    if let Some(outline) = outline {
+                        let engine = self.engine.lock().unwrap();
+                        let rows = lookup::translations(&engine, &outline);
+                        if let Some(row) = rows.get(*selected) {
+                            *edit = Some(row.translation.clone().unwrap_or_default());
+                        }
+                    }
+                }
+                KeyCode::Delete => {
+                    self.message = Self::set(&self.engine, input, *selected, None);
+                    self.drill_words = None;
+                    self.hint = None;
+                }
+                _ => edit_text(input, key),
+            },
+            Mode::Strokes {
+                input,
+                selected,
+                edit: edit @ Some(_),
+            } => match key.code {
+                KeyCode::Esc => *edit = None,
+                KeyCode::Enter => {
+                    let translation = edit.take();
+                    self.message =
+                        Self::set(&self.engine, input, *selected, translation.as_deref());
+                    self.drill_words = None;
+                    self.hint = None;
+                }
+                _ => edit_text(edit.as_mut().unwrap(), key),
+            },
+            Mode::Drill(d, review) => match key.code {
+                KeyCode::Esc => self.mode = Mode::Normal,
+                KeyCode::Enter if d.is_done() && review.is_some() => self.start_review(),
+                KeyCode::Enter if d.is_done() => self.start_drill(),
+                _ => {}
+            },
+            Mode::Dictionaries(selected) => {
+                let mut engine = self.engine.lock().unwrap();
+                let n = engine.dictionaries().count();
+                let Some(dict) = engine.dictionaries().nth(*selected) else {
+                    return;
+                };
+                let (id, enabled) = (dict.id(), dict.is_enabled());
+                match key.code {
+                    KeyCode::Esc => {
+                        drop(engine);
+                        self.mode = Mode::Normal;
+                    }
+                    KeyCode::Up => *selected = selected.saturating_sub(1),
+                    KeyCode::Down => *selected = (*selected + 1).min(n - 1),
+                    KeyCode::Enter => {
+                        engine.set_dictionary_enabled(id, !enabled);
+                    }
+                    // The selection moves with the dictionary
+                    KeyCode::Char('+') => {
+                        *selected = selected.saturating_sub(1);
+                        engine.set_dictionary_priority(id, *selected);
+                    }
+                    KeyCode::Char('-') => {
+                        *selected = (*selected + 1).min(n - 1);
+                        engine.set_dictionary_priority(id, *selected);
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    // A passage from the drill text, or words from the dictionaries.
+    fn start_drill(&mut self) {
+        let seed = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos() as u64;
+        let target = match &self.drill.text {
+            Some(path) => match fs::read_to_string(path) {
+                Ok(text) => drill::pick(&drill::passages(&text), 1, seed).concat(),
+                Err(e) => {
+                    self.errors
+                        .push(format!("Could not read the drill text: {}", e));
+                    return;
+                }
+            },
+            None => {
+                let engine = self.engine.lock().unwrap();
+                let enabled: Vec<usize> = engine
+                    .dictionaries()
+                    .filter(|x| x.is_enabled())
+                    .map(|x| x.id())
+                    .collect();
+                let words = match self.drill_words.take() {
+                    Some((ids, words)) if ids == enabled => words,
+                    _ => drill::dictionary_words(&engine),
+                };
+                drop(engine);
+                let target = drill::pick(&words, self.drill.words, seed).join(" ");
+                self.drill_words = Some((enabled, words));
+                target
+            }
+        };
+        if target.is_empty() {
+            self.errors.push("Nothing to practise".to_string());
+            return;
+        }
+        self.mode = Mode::Drill(Drill::new(&target, SystemTime::now()), None);
+    }
+
+    // A drill of the trouble words that are due, after looking through the
+    // stroke log for new ones.
+    fn start_review(&mut self) {
+        let deck = (|| -> std::io::Result<Deck> {
+            let mut deck = Deck::load(review::path())?;
+            let entries: Vec<tape::Entry> = history::read(review::LOG_DAYS)?
+                .into_iter()
+                .flat_map(|x| x.1)
+                .collect();
+            deck.scan(&entries);
+            deck.save()?;
+            Ok(deck)
+        })();
+        let deck = match deck {
+            Ok(deck) => deck,
+            Err(e) => {
+                self.errors
+                    .push(format!("Could not read the review state: {}", e));
+                return;
+            }
+        };
+        let seed = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos() as u64;
+        let words = deck.due(Local::now().date_naive(), review::REVIEW_LEN);
+        let words = drill::pick(&words, words.len(), seed);
+        self.message = Ok(String::new());
+        self.mode = Mode::Drill(Drill::new(&words.join(" "), SystemTime::now()), Some(deck));
+    }
+
+    // The target with the words written so far marked, the results, and a
+    // hint once the writer has stopped for a while.
+    fn draw_drill(&mut self) -> Result<(), std::io::Error> {
+        let Mode::Drill(d, review) = &self.mode else {
+            return Ok(());
+        };
+        let stdout = &mut self.stdout;
+        let dim = self.dim;
+        let x = dim.0.saturating_sub(LOOKUP_WIDTH as u16) / 2;
+        let mut y = tape_top(dim).saturating_sub(1);
+        let title = if review.is_some() { "Review" } else { "Drill" };
+        queue!(
+            stdout,
+            cursor::MoveTo(x, y),
+            Print(title.bold()),
+            cursor::MoveTo(x, y + 2)
+        )?;
+        y += 2;
+        if d.target().is_empty() {
+            queue!(stdout, Print("Nothing is due for review".green()))?;
+            return Ok(());
+        }
+
+        let current = d.current();
+        let mut len = 0;
+        for (i, word) in d.target().iter().enumerate() {
+            if len > 0 && len + 1 + word.chars().count() > LOOKUP_WIDTH {
+                y += 1;
+                len = 0;
+                queue!(stdout, cursor::MoveTo(x, y))?;
+            }
+            if len > 0 {
+                queue!(stdout, Print(" "))?;
+                len += 1;
+            }
+            len += word.chars().count();
+            let word = word.as_str();
+            match d.is_correct(i) {
+                _ if i == current => queue!(stdout, Print(word.bold().underlined()))?,
+                Some(true) => queue!(stdout, Print(word.green()))?,
+                Some(false) => queue!(stdout, Print(word.red()))?,
+                None => queue!(stdout, Print(word))?,
+            }
+        }
+
+        // The end of what's been written
+        let written = d.written().join(" ");
+        let skip = written.chars().count().saturating_sub(LOOKUP_WIDTH - 2);
+        let written: String = written.chars().skip(skip).collect();
+        let results = format!(
+            "{:.0}% accuracy   {:.0} WPM   {} hesitations",
+            d.accuracy() * 100.0,
+            d.wpm(),
+            d.hesitations().len()
+        );
+        queue!(
+            stdout,
+            cursor::MoveTo(x, y + 2),
+            terminal::Clear(terminal::ClearType::CurrentLine),
+            Print(format!("> {}", written)),
+            cursor::MoveTo(x, y + 4),
+            terminal::Clear(terminal::ClearType::CurrentLine),
+            Print(results),
+            cursor::MoveTo(x, y + 6),
+            terminal::Clear(terminal::ClearType::CurrentLine),
+        )?;
+
+        let delay = self.drill.hint_delay().unwrap_or_default();
+        if d.is_done() {
+            queue!(stdout, Print("Done".green()))?;
+            if !d.hesitations().is_empty() {
+                let s = format!("  Hesitated on: {}", d.hesitations().join(", "));
+                queue!(stdout, Print(fit(&s, LOOKUP_WIDTH - 4).dark_grey()))?;
+            }
+            if review.is_some() {
+                draw_message(stdout, dim, &self.message)?;
+            }
+            draw_keys(stdout, dim, "Enter: another drill   Esc: back")?;
+        } else if let Some(word) = d.hint(SystemTime::now(), delay) {
+            // Looked up once for each word, rather than on every tick
+            if self.hint.as_ref().is_none_or(|x| x.0 != word) {
+                let engine = self.engine.lock().unwrap();
+                let outlines: Vec<String> = lookup::outlines(&engine, word)
+                    .into_iter()
+                    .map(|x| x.0)
+                    .take(3)
+                    .collect();
+                let s = if outlines.is_empty() {
+                    format!("{}: not in the dictionaries", word)
+                } else {
+                    format!("{}: {}", word, outlines.join("  "))
+                };
+                self.hint = Some((word.to_string(), s));
+            }
+            let hint = self.hint.as_ref().map_or("", |x| x.1.as_str());
+            queue!(stdout, Print(hint.yellow()))?;
+        }
+        Ok(())
+    }
+
+    // Change the translation of the outline in a dictionary, with a message saying how it went.
+    fn set(
+        engine: &Mutex<Engine>,
+        input: &str,
+        dictionary: usize,
+        translation: Option<&str>,
+    ) -> Result<String, String> {
+        let outline = engine::parse_outline(input)
+            .filter(|x| !x.is_empty())
+            .ok_or("Not a valid outline")?;
+        let mut engine = engine.lock().unwrap();
+        lookup::set(&mut engine, dictionary, &outline, translation)?;
+        Ok(match translation {
+            Some(t) => format!("{} is now {}", lookup::steno(&outline), t),
+            None => format!("Removed {}", lookup::steno(&outline)),
+        })
+    }
+}
+
+// Schedule each word of a finished review by how it went, and save it.
+fn finish_review(drill: &Drill, deck: &mut Deck) -> Result<String, String> {
+    let today = Local::now().date_naive();
+    let mut good = 0;
+    for (i, word) in drill.target().iter().enumerate() {
+        deck.review(word, !drill.struggled(i), today);
+        good += usize::from(!drill.struggled(i));
+    }
+    deck.save()
+        .map_err(|e| format!("Could not save the review: {}", e))?;
+    Ok(format!(
+        "{} of {} words went well",
+        good,
+        drill.target().len()
+    ))
+}
+
+// Type into or backspace a line of text.
+fn edit_text(text: &mut String, key: KeyEvent) {
+    match key.code {
+        KeyCode::Char(c) => text.push(c),
+        KeyCode::Backspace => {
+            text.pop();
+        }
+        _ => {}
+    }
+}
+
+// A title, the text being looked up if there is any, and a row for each
+// result. The selected row can have the text it's being changed to.
+fn draw_lookup(
+    stdout: &mut Stdout,
+    dim: (u16, u16),
+    title: &str,
+    input: Option<&str>,
+    rows: &[Row],
+    selected: Option<(usize, Option<&str>)>,
+) -> Result<(), std::io::Error> {
+    let x = dim.0.saturating_sub(LOOKUP_WIDTH as u16) / 2;
+    let y = tape_top(dim).saturating_sub(1);
+    queue!(stdout, cursor::MoveTo(x, y), Print(title.bold()))?;
+    if let Some(input) = input {
+        queue!(
+            stdout,
+            cursor::MoveTo(x, y + 2),
+            Print(format!("> {}_", input))
+        )?;
+    }
+    let width = LOOKUP_WIDTH - 16 - SOURCE_LEN;
+    for (i, (left, right, note, used)) in rows.iter().enumerate() {
+        let is_selected = selected.is_some_and(|x| x.0 == i);
+        let right = match selected {
+            Some((j, Some(edit))) if j == i => format!("{}_", edit),
+            _ => right.clone(),
+        };
+        let s = format!(
+            "{} {}  {} {}",
+            if is_selected { '>' } else { ' ' },
+            fit(left, SOURCE_LEN + 2),
+            fit(&right, width),
+            fit(note, 10),
+        );
+        queue!(stdout, cursor::MoveTo(x, y + 4 + i as u16))?;
+        if is_selected {
+            queue!(stdout, Print(s.reverse()))?;
+        } else if *used {
+            queue!(stdout, Print(s))?;
+        } else {
+            queue!(stdout, Print(s.dark_grey()))?;
+        }
+    }
+    if rows.is_empty() && input.is_some_and(|x| !x.is_empty()) {
+        queue!(
+            stdout,
+            cursor::MoveTo(x, y + 4),
+            Print("Nothing found".dark_grey())
+        )?;
+    }
+    Ok(())
+}
+
+fn draw_message(
+    stdout: &mut Stdout,
+    dim: (u16, u16),
+    message: &Result<String, String>,
+) -> Result<(), std::io::Error> {
+    let y = (dim.1 + DISPLAY_LEN) / 2 + 5;
+    let s = match message {
+        Ok(s) | Err(s) => s.chars().take(dim.0.into()).collect::<String>(),
+    };
+    queue!(
+        stdout,
+        cursor::MoveTo((dim.0 - s.chars().count() as u16) / 2, y),
+        Print(match message {
+            Ok(_) => s.green(),
+            Err(_) => s.red(),
+        }),
+    )?;
+    Ok(())
+}
+
+// The keys that do something, at the bottom of the screen.
+fn draw_keys(stdout: &mut Stdout, dim: (u16, u16), keys: &str) -> Result<(), std::io::Error> {
+    queue!(
+        stdout,
+        cursor::MoveTo(dim.0.saturating_sub(keys.len() as u16) / 2, dim.1 - 1),
+        terminal::Clear(terminal::ClearType::CurrentLine),
+        Print(keys.dark_grey()),
+    )?;
+    Ok(())
+}
+fn draw_stroke_display(
+    stdout: &mut std::io::Stdout,
+    dim: (u16, u16),
+    s: &VecDeque<StrokeEvent>,
+) -> Result<(), std::io::Error> {
+    draw_diagram(stdout, dim, s.back().map_or(0, |x| x.stroke))?;
+
+    let w = TAPE_WIDTH + 2;
+    let x = (dim.0 - w as u16 - 2) / 2;
+    let y = tape_top(dim).saturating_sub(1);
+    queue!(
+        stdout,
+        cursor::MoveTo(x, y),
+        Print(format!("┌{}┐", "─".repeat(w)).dark_grey()),
+    )?;
+    for i in 1..=DISPLAY_LEN {
+        queue!(
+            stdout,
+            cursor::MoveTo(x, y + DISPLAY_LEN + 1 - i),
+            Print("│ ".dark_grey()),
+        )?;
+        match s.len().checked_sub(i as usize).and_then(|i| s.get(i)) {
+            Some(event) => draw_tape_entry(stdout, event)?,
+            None => queue!(stdout, Print(" ".repeat(TAPE_WIDTH)))?,
+        }
+        queue!(stdout, Print(" │".dark_grey()))?;
+    }
+    queue!(
+        stdout,
+        cursor::MoveTo(x, y + DISPLAY_LEN + 1),
+        Print(format!("└{}┘", "─".repeat(w)).dark_grey()),
+    )?;
+    Ok(())
+}
+
+// Words per minute over the rolling windows, and a graph of the last few minutes.
+fn draw_speed(
+    stdout: &mut std::io::Stdout,
+    dim: (u16, u16),
+    speed: &Speed,
+) -> Result<(), std::io::Error> {
+    let now = SystemTime::now();
+    let short = speed.window(now, Duration::from_secs(10));
+    let long = speed.window(now, Duration::from_secs(60));
+    let session = speed.session();
+    let s = format!(
+        "WPM  10s: {:.0}  1min: {:.0}  session: {:.0}  peak: {:.0}",
+        short.words,
+        long.words,
+        session.words,
+        speed.peak()
+    );
+    let s2 = format!(
+        "5-char WPM  10s: {:.0}  1min: {:.0}  session: {:.0}",
+        short.standard, long.standard, session.standard
+    );
+    let s3 = sparkline(&speed.history(now, GRAPH_PERIOD, GRAPH_LEN));
+    let s3_len = s3.chars().count() as u16;
+    queue!(
+        stdout,
+        cursor::MoveTo((dim.0 - s.len() as u16) / 2, (dim.1 + DISPLAY_LEN) / 2 + 2),
+        terminal::Clear(terminal::ClearType::CurrentLine),
+        Print(s),
+        cursor::MoveTo((dim.0 - s2.len() as u16) / 2, (dim.1 + DISPLAY_LEN) / 2 + 3),
+        terminal::Clear(terminal::ClearType::CurrentLine),
+        Print(s2),
+        cursor::MoveTo((dim.0 - s3_len) / 2, (dim.1 + DISPLAY_LEN) / 2 + 4),
+        terminal::Clear(terminal::ClearType::CurrentLine),
+        Print(s3.dark_grey()),
+    )?;
+    Ok(())
+}
+
+// Bars for each value, scaled to the largest one.
+fn sparkline(values: &[f64]) -> String {
+    const BARS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+    let max = values.iter().cloned().fold(0.0, f64::max);
+    values
+        .iter()
+        .map(|x| {
+            if max == 0.0 {
+                BARS[0]
+            } else {
+                BARS[((x / max) * (BARS.len() - 1) as f64).round() as usize]
+            }
+        })
+        .collect()
+}
+
+// Half the space left around the paper tape, which is above it.
+fn tape_top(dim: (u16, u16)) -> u16 {
+    dim.1.saturating_sub(DISPLAY_LEN) / 2
+}
+
+// Rows the keyboard diagram takes, or none if the terminal is too short for
+// it and the status lines above it.
+fn diagram_height(dim: (u16, u16)) -> u16 {
+    if tape_top(dim) >= 6 + DIAGRAM_HEIGHT {
+        DIAGRAM_HEIGHT
+    } else {
+        0
+    }
+}
+
+// A status line's row, counted up from the paper tape and above the diagram.
+fn status_row(dim: (u16, u16), n: u16) -> u16 {
+    tape_top(dim).saturating_sub(n + diagram_height(dim))
+}
+
+// The last stroke on a keyboard, with the pressed keys highlighted.
+fn draw_diagram(
+    stdout: &mut std::io::Stdout,
+    dim: (u16, u16),
+    stroke: u32,
+) -> Result<(), std::io::Error> {
+    let rows = diagram::rows(stroke);
+    let w = rows.iter().map(|x| x.len()).max().unwrap_or(0) as u16 * 3;
+    let x = (dim.0 - w) / 2;
+    // Rows are drawn upwards from the one above the paper tape
+    if diagram_height(dim) == 0 {
+        return Ok(());
+    }
+    let bottom = tape_top(dim) - 3;
+    for i in 0..DIAGRAM_HEIGHT - 1 {
+        queue!(
+            stdout,
+            cursor::MoveTo(0, bottom - i),
+            terminal::Clear(terminal::ClearType::CurrentLine),
+        )?;
+    }
+    for (i, row) in rows.iter().rev().enumerate() {
+        let row_w = row.len() as u16 * 3;
+        queue!(
+            stdout,
+            cursor::MoveTo(x + (w - row_w) / 2, bottom - i as u16)
+        )?;
+        for key in row {
+            let s = match key {
+                Some((c, _)) => format!(" {} ", c),
+                None => "   ".to_string(),
+            };
+            match key {
+                Some((_, true)) => queue!(stdout, Print(s.black().on_green()))?,
+                _ => queue!(stdout, Print(s.dark_grey()))?,
+            }
+        }
+    }
+    Ok(())
+}
+
+// Stroke, mark, translation and dictionary, padded to TAPE_WIDTH.
+fn draw_tape_entry(
+    stdout: &mut std::io::Stdout,
+    event: &StrokeEvent,
+) -> Result<(), std::io::Error> {
+    let (mark, text) = tape::describe(&event.outcome);
+    let text = fit(&text, TRANSLATION_LEN);
+    let source = fit(tape::source(&event.outcome), SOURCE_LEN);
+    queue!(
+        stdout,
+        Print(engine::id_to_steno(event.stroke).black()),
+        Print(" "),
+        Print(match mark {
+            '?' => mark.red(),
+            '<' => mark.yellow(),
+            _ => mark.dark_grey(),
+        }),
+        Print(" "),
+        Print(text),
+        Print(" "),
+        Print(source.dark_grey()),
+    )?;
+    Ok(())
+}
+
+// Truncate or pad text to a number of characters.
+fn fit(s: &str, len: usize) -> String {
+    format!("{:len$}", s.chars().take(len).collect::<String>())
+}
+
+fn draw_machine_status(
+    stdout: &mut std::io::Stdout,
+    dim: (u16, u16),
+    path: &str,
+) -> Result<(), std::io::Error> {
+    let s = if path.is_empty() {
+        "disconnected"
+    } else {
+        path
+    };
+    queue!(
+        stdout,
+        cursor::MoveTo((dim.0 - s.len() as u16) / 2, status_row(dim, 4)),
+        terminal::Clear(terminal::ClearType::CurrentLine),
+        Print(if !path.is_empty() { s.green() } else { s.red() })
+    )?;
+    Ok(())
+}
+fn draw_output_status(
+    stdout: &mut std::io::Stdout,
+    dim: (u16, u16),
+    on: bool,
+) -> Result<(), std::io::Error> {
+    let s = if on { "output on" } else { "output off" };
+    queue!(
+        stdout,
+        cursor::MoveTo((dim.0 - s.len() as u16) / 2, status_row(dim, 6)),
+        terminal::Clear(terminal::ClearType::CurrentLine),
+        Print(if on { s.green() } else { s.red() }),
+    )?;
+    Ok(())
+}
+fn draw_profile(
+    stdout: &mut std::io::Stdout,
+    dim: (u16, u16),
+    profile: &str,
+) -> Result<(), std::io::Error> {
+    let s = format!("profile: {}", profile);
+    queue!(
+        stdout,
+        cursor::MoveTo((dim.0 - s.len() as u16) / 2, status_row(dim, 5)),
+        terminal::Clear(terminal::ClearType::CurrentLine),
+        Print(s.dark_grey()),
+    )?;
+    Ok(())
+}
+fn draw_dict_status(
+    stdout: &mut std::io::Stdout,
+    dim: (u16, u16),
+    n: usize,
+) -> Result<(), std::io::Error> {
+    let s = format!("{} dictionaries loaded", n);
+    queue!(
+        stdout,
+        cursor::MoveTo((dim.0 - s.len() as u16) / 2, status_row(dim, 3)),
+        terminal::Clear(terminal::ClearType::CurrentLine),
+        Print(s),
+    )?;
+    Ok(())
+}
+
+fn draw_session_stats(
+    stdout: &mut std::io::Stdout,
+    dim: (u16, u16),
+    stats: &Stats,
+) -> Result<(), std::io::Error> {
+    let s = format!(
+        "Session: {:.2} strokes/word    {:.1}% undos",
+        stats.strokes_per_word(),
+        stats.undo_rate() * 100.0
+    );
+    queue!(
+        stdout,
+        cursor::MoveTo((dim.0 - s.len() as u16) / 2, (dim.1 + DISPLAY_LEN) / 2 + 5),
+        terminal::Clear(terminal::ClearType::CurrentLine),
+        Print(s),
+    )?;
+    Ok(())
+}
+
+// Problems are listed below the stroke display, newest last.
+fn draw_errors(
+    stdout: &mut std::io::Stdout,
+    dim: (u16, u16),
+    errors: &[String],
+) -> Result<(), std::io::Error> {
+    let y = (dim.1 + DISPLAY_LEN) / 2 + 7;
+    for (i, e) in errors.iter().enumerate() {
+        let s: String = e.chars().take(dim.0.into()).collect();
+        queue!(
+            stdout,
+            cursor::MoveTo((dim.0 - s.chars().count() as u16) / 2, y + i as u16),
+            terminal::Clear(terminal::ClearType::CurrentLine),
+            Print(s.red()),
+        )?;
+    }
+    Ok(())
+}
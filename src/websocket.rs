@@ -0,0 +1,179 @@
+/*
+    Live stroke feed over WebSocket, for on-screen overlays and other
+    displays. Each stroke is broadcast as a JSON text message, with the same
+    fields as the control API's stroke events plus the word being written.
+    Clients only listen; anything they send is ignored, apart from closing
+    the connection.
+*/
+
+use crate::event;
+use crate::Ui;
+use std::io;
+use std::net::{TcpListener, TcpStream};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use tungstenite::{Message, WebSocket};
+
+// Clients that take longer to connect or be written to are dropped, so they
+// don't hold up the others.
+const TIMEOUT: Duration = Duration::from_secs(1);
+// How often clients are checked for having closed the connection, and how
+// long that takes
+const POLL_RATE: Duration = Duration::from_millis(100);
+const POLL_TIMEOUT: Duration = Duration::from_millis(1);
+// Only the end of the text is needed to tell which word is being written.
+const TEXT_LEN: usize = 100;
+
+// Where to send each client's messages, to the thread looking after it
+type Clients = Arc<Mutex<Vec<mpsc::Sender<String>>>>;
+
+// Listen on the address, returning where to send the messages that strokes
+// are broadcast for.
+pub fn start(address: &str) -> io::Result<mpsc::Sender<Ui>> {
+    Ok(serve(TcpListener::bind(address)?, || {}))
+}
+
+// Like start, calling accepted whenever a client has connected.
+fn serve(listener: TcpListener, accepted: impl Fn() + Send + Sync + 'static) -> mpsc::Sender<Ui> {
+    let clients: Clients = Arc::default();
+    let connected = clients.clone();
+    let accepted = Arc::new(accepted);
+    thread::spawn(move || {
+        for stream in listener.incoming().map_while(Result::ok) {
+            let clients = connected.clone();
+            let accepted = accepted.clone();
+            thread::spawn(move || {
+                stream.set_read_timeout(Some(TIMEOUT)).ok();
+                stream.set_write_timeout(Some(TIMEOUT)).ok();
+                let Ok(socket) = tungstenite::accept(stream) else {
+                    return;
+                };
+                socket.get_ref().set_read_timeout(Some(POLL_TIMEOUT)).ok();
+                let (tx, rx) = mpsc::channel();
+                clients.lock().unwrap().push(tx);
+                accepted();
+                serve_client(socket, rx);
+            });
+        }
+    });
+
+    let (tx, rx) = mpsc::channel::<Ui>();
+    thread::spawn(move || {
+        let mut text = String::new();
+        for msg in rx {
+            let (Ui::Stroke(stroke), Some(mut event)) = (&msg, event::event(&msg)) else {
+                continue;
+            };
+            for _ in stroke.deleted.chars() {
+                text.pop();
+            }
+            text.push_str(&stroke.added);
+            if text.len() > 2 * TEXT_LEN {
+                let cut = (text.len() - TEXT_LEN..text.len())
+                    .find(|x| text.is_char_boundary(*x))
+                    .unwrap();
+                text.drain(..cut);
+            }
+            event["word"] = text.split_whitespace().last().unwrap_or_default().into();
+
+            let message = event.to_string();
+            clients
+                .lock()
+                .unwrap()
+                .retain(|x| x.send(message.clone()).is_ok());
+        }
+    });
+    tx
+}
+
+// Send a client its messages until it closes the connection, or can't be
+// written to.
+fn serve_client(mut socket: WebSocket<TcpStream>, messages: mpsc::Receiver<String>) {
+    loop {
+        match messages.recv_timeout(POLL_RATE) {
+            Ok(message) => {
+                if socket.send(Message::text(message)).is_err() {
+                    return;
+                }
+                continue;
+            }
+            Err(mpsc::RecvTimeoutError::Timeout) => {}
+            Err(mpsc::RecvTimeoutError::Disconnected) => return,
+        }
+        // Reading answers a close, after which the connection is closed
+        match socket.read() {
+            Ok(_) => {}
+            Err(tungstenite::Error::Io(e))
+                if matches!(
+                    e.kind(),
+                    io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                ) => {}
+            Err(_) => return,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use moreover::engine::steno_to_id;
+    use moreover::{Outcome, StrokeEvent};
+    use serde_json::Value;
+    use std::time::SystemTime;
+
+    #[test]
+    fn broadcast() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let (accepted_tx, accepted) = mpsc::channel();
+        let tx = serve(listener, move || accepted_tx.send(()).unwrap());
+        let stream = TcpStream::connect(address).unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        let (mut socket, _) = tungstenite::client(format!("ws://{}", address), stream).unwrap();
+        // The server adds the client once the handshake is done on its side
+        accepted.recv_timeout(Duration::from_secs(5)).unwrap();
+
+        let stroke = |deleted: &str, added: &str| {
+            Ui::Stroke(StrokeEvent {
+                stroke: steno_to_id("KAt"),
+                time: SystemTime::now(),
+                duration: 0,
+                actions: 1,
+                outcome: Outcome::Untranslated,
+                deleted: deleted.to_string(),
+                added: added.to_string(),
+            })
+        };
+        tx.send(stroke("", " the")).unwrap();
+        tx.send(Ui::Output(false)).unwrap();
+        tx.send(stroke("", " cat")).unwrap();
+        tx.send(stroke(" cat", " cats")).unwrap();
+
+        let mut read = || -> Value {
+            let message = socket.read().unwrap();
+            serde_json::from_str(message.to_text().unwrap()).unwrap()
+        };
+        assert_eq!(read()["word"], "the");
+        let event = read();
+        assert_eq!(event["word"], "cat");
+        assert_eq!(event["stroke"], "KAt");
+        let event = read();
+        assert_eq!(event["word"], "cats");
+        assert_eq!(event["deleted"], " cat");
+        assert_eq!(event["added"], " cats");
+
+        // Closing is answered, rather than the client being written to until
+        // it times out
+        socket.close(None).unwrap();
+        loop {
+            match socket.read() {
+                Ok(_) => {}
+                Err(tungstenite::Error::ConnectionClosed) => break,
+                Err(e) => panic!("{}", e),
+            }
+        }
+    }
+}
@@ -0,0 +1,131 @@
+/*
+    Watches which window is focused on X11, using the window manager's
+    _NET_ACTIVE_WINDOW property, so that settings can follow the application
+    being written in.
+*/
+
+use std::error::Error;
+use x11rb::connection::Connection;
+use x11rb::protocol::xproto::{
+    Atom, AtomEnum, ChangeWindowAttributesAux, ConnectionExt, EventMask, Window as XWindow,
+};
+use x11rb::protocol::Event;
+use x11rb::rust_connection::RustConnection;
+
+// Longest title read, in 32 bit units
+const TITLE_LEN: u32 = 1024;
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Window {
+    // The application's part of WM_CLASS, like "firefox" or "Code"
+    pub class: String,
+    pub title: String,
+}
+
+struct Atoms {
+    active_window: Atom,
+    name: Atom,
+    // The title set by older applications
+    old_name: Atom,
+}
+
+// Call back with the focused window whenever it, or its title, changes, until
+// the callback returns false.
+pub fn watch(mut focused: impl FnMut(Window) -> bool) -> Result<(), Box<dyn Error>> {
+    let (conn, screen) = x11rb::connect(None)?;
+    let root = conn.setup().roots[screen].root;
+    let atoms = Atoms {
+        active_window: conn
+            .intern_atom(false, b"_NET_ACTIVE_WINDOW")?
+            .reply()?
+            .atom,
+        name: conn.intern_atom(false, b"_NET_WM_NAME")?.reply()?.atom,
+        old_name: AtomEnum::WM_NAME.into(),
+    };
+    let property_changes = ChangeWindowAttributesAux::new().event_mask(EventMask::PROPERTY_CHANGE);
+    conn.change_window_attributes(root, &property_changes)?
+        .check()?;
+
+    let mut active = None;
+    let mut last = None;
+    loop {
+        let window = active_window(&conn, root, &atoms)?;
+        if window != active {
+            // The window may be gone already, and the error that follows is
+            // ignored along with any others
+            if let Some(old) = active {
+                let no_changes = ChangeWindowAttributesAux::new().event_mask(EventMask::NO_EVENT);
+                conn.change_window_attributes(old, &no_changes)?;
+            }
+            if let Some(new) = window {
+                conn.change_window_attributes(new, &property_changes)?;
+            }
+            conn.flush()?;
+            active = window;
+        }
+        let info = match active {
+            Some(window) => describe(&conn, window, &atoms).unwrap_or_default(),
+            None => Window::default(),
+        };
+        if last.as_ref() != Some(&info) {
+            last = Some(info.clone());
+            if !focused(info) {
+                return Ok(());
+            }
+        }
+
+        // Wait for the focus or the title to change
+        loop {
+            match conn.wait_for_event()? {
+                Event::PropertyNotify(e)
+                    if (e.window == root && e.atom == atoms.active_window)
+                        || (Some(e.window) == active
+                            && (e.atom == atoms.name || e.atom == atoms.old_name)) =>
+                {
+                    break
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+fn active_window(
+    conn: &RustConnection,
+    root: XWindow,
+    atoms: &Atoms,
+) -> Result<Option<XWindow>, Box<dyn Error>> {
+    let reply = conn
+        .get_property(false, root, atoms.active_window, AtomEnum::WINDOW, 0, 1)?
+        .reply()?;
+    Ok(reply
+        .value32()
+        .and_then(|mut x| x.next())
+        .filter(|x| *x != 0))
+}
+
+fn describe(
+    conn: &RustConnection,
+    window: XWindow,
+    atoms: &Atoms,
+) -> Result<Window, Box<dyn Error>> {
+    let property = |atom: Atom| -> Result<Vec<u8>, Box<dyn Error>> {
+        let reply = conn
+            .get_property(false, window, atom, AtomEnum::ANY, 0, TITLE_LEN)?
+            .reply()?;
+        Ok(reply.value)
+    };
+    // WM_CLASS is the instance name and then the class name
+    let class = property(AtomEnum::WM_CLASS.into())?;
+    let mut parts = class.split(|x| *x == 0).filter(|x| !x.is_empty());
+    let instance = parts.next().unwrap_or_default();
+    let class = parts.next().unwrap_or(instance);
+    let mut title = property(atoms.name)?;
+    if title.is_empty() {
+        title = property(atoms.old_name)?;
+    }
+    Ok(Window {
+        class: String::from_utf8_lossy(class).into_owned(),
+        title: String::from_utf8_lossy(&title).into_owned(),
+    })
+}
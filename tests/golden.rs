@@ -0,0 +1,86 @@
+/*
+    Golden-file tests for the translation pipeline.
+    Each file in tests/golden is a small dictionary, a list of strokes, and the
+    text expected after applying every stroke's deletions and additions.
+*/
+
+use moreover::buffer::Buffer;
+use moreover::engine::{dictionary::TreeDict, parse_outline, Engine};
+use std::fs;
+use std::path::Path;
+
+struct Case {
+    dictionary: String,
+    strokes: String,
+    output: String,
+}
+
+// Cases are split into sections by "--- dictionary", "--- strokes" and
+// "--- output" lines. Lines before the first section are comments.
+fn parse_case(s: &str) -> Case {
+    let mut case = Case {
+        dictionary: String::new(),
+        strokes: String::new(),
+        output: String::new(),
+    };
+    let mut section = None;
+    for line in s.split_inclusive('\n') {
+        match line.trim_end() {
+            "--- dictionary" => section = Some(&mut case.dictionary),
+            "--- strokes" => section = Some(&mut case.strokes),
+            "--- output" => section = Some(&mut case.output),
+            _ => {
+                if let Some(s) = &mut section {
+                    s.push_str(line);
+                }
+            }
+        }
+    }
+    // The output ends at the end of the file, not with a newline
+    if case.output.ends_with('\n') {
+        case.output.pop();
+    }
+    case
+}
+
+fn run_case(case: &Case) -> Result<String, String> {
+    let mut engine = Engine::new();
+    engine.add_dictionary(Box::new(TreeDict::from_reader(case.dictionary.as_bytes())));
+
+    let mut buffer = Buffer::new();
+    for line in case.strokes.lines() {
+        let strokes = parse_outline(line).ok_or(format!("invalid stroke \"{}\"", line))?;
+        for stroke in strokes {
+            let (del, add) = engine.process_stroke(stroke);
+            buffer.apply(&del, &add);
+        }
+    }
+    Ok(buffer.text().to_string())
+}
+
+#[test]
+fn golden() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden");
+    let mut paths: Vec<_> = fs::read_dir(dir)
+        .unwrap()
+        .map(|x| x.unwrap().path())
+        .filter(|x| x.extension().is_some_and(|x| x == "txt"))
+        .collect();
+    paths.sort();
+    assert!(!paths.is_empty());
+
+    let mut failures = vec![];
+    for path in paths {
+        let case = parse_case(&fs::read_to_string(&path).unwrap());
+        let name = path.file_name().unwrap().to_string_lossy();
+        match run_case(&case) {
+            Ok(output) if output == case.output => {}
+            Ok(output) => failures.push(format!(
+                "{}\n  expected: {:?}\n  actual:   {:?}",
+                name, case.output, output
+            )),
+            Err(e) => failures.push(format!("{}: {}", name, e)),
+        }
+    }
+    assert!(failures.is_empty(), "\n{}", failures.join("\n"));
+}